@@ -0,0 +1,5 @@
+-- SPDX-License-Identifier: MIT
+-- Copyright (c) 2025 coppamocha
+
+-- Bundled lang-script for C++ projects, see liebe/cc.lua for the `[cc]` settings
+require("liebe.cc").setup("cpp")
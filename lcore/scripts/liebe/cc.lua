@@ -0,0 +1,203 @@
+-- SPDX-License-Identifier: MIT
+-- Copyright (c) 2025 coppamocha
+
+--[[
+C/C++ support shared by the bundled c.lua and cpp.lua lang-scripts.
+Every source is compiled by its own task, the objects of a target are then
+linked into an executable, a static or a shared library. Settings come from
+the `[cc]` section of liebe.toml, `[cc.targets.<name>]` adds to them for a
+single target.
+]]
+local M = {}
+
+local strings = { type = "array", items = "string" }
+
+local target_schema = {
+    ["include-dirs"] = strings,
+    defines = strings,
+    flags = strings,
+    ["link-flags"] = strings,
+    libs = strings,
+    library = { type = "enum", values = { "static", "shared" } },
+}
+
+local schema = {
+    cc = "string",
+    cxx = "string",
+    ar = "string",
+    std = "string",
+    ["cxx-std"] = "string",
+    ["include-dirs"] = strings,
+    defines = strings,
+    flags = strings,
+    ["link-flags"] = strings,
+    libs = strings,
+    targets = { type = "map", values = target_schema },
+}
+
+local C_EXT = { c = true }
+local CXX_EXT = { cc = true, cpp = true, cxx = true, ["c++"] = true, C = true }
+
+local function extension(path)
+    return path:match("%.([^./]+)$")
+end
+
+local function append(list, items)
+    for _, item in ipairs(items or {}) do
+        list[#list + 1] = item
+    end
+    return list
+end
+
+local function prefixed(list, prefix, items)
+    for _, item in ipairs(items or {}) do
+        list[#list + 1] = prefix .. item
+    end
+    return list
+end
+
+local function tool(conf, key, env, fallback)
+    return conf[key] or os.getenv(env) or fallback
+end
+
+-- Flags shared by every object of a target: profile first, then liebe.toml
+local function compile_flags(conf, target_conf, profile, cxx)
+    local flags = {}
+    local std = cxx and conf["cxx-std"] or not cxx and conf.std
+    if std then
+        flags[#flags + 1] = "-std=" .. std
+    end
+    if profile.opt_level then
+        flags[#flags + 1] = "-O" .. profile.opt_level
+    end
+    if profile.debug then
+        flags[#flags + 1] = "-g"
+    end
+    prefixed(flags, "-D", profile.defines)
+    append(flags, profile.flags)
+    for _, c in ipairs({ conf, target_conf }) do
+        prefixed(flags, "-I", c["include-dirs"])
+        prefixed(flags, "-D", c.defines)
+        append(flags, c.flags)
+    end
+    return flags
+end
+
+local function link_flags(conf, target_conf)
+    local flags = {}
+    for _, c in ipairs({ conf, target_conf }) do
+        append(flags, c["link-flags"])
+        prefixed(flags, "-l", c.libs)
+    end
+    return flags
+end
+
+-- Libraries built by the targets this one depends on, in link order
+local function dep_libraries(deps)
+    local libs = {}
+    for _, dep in ipairs(deps) do
+        for _, output in ipairs(liebe.outputs(dep, true)) do
+            local ext = extension(output)
+            if ext == "a" or ext == "so" then
+                libs[#libs + 1] = output
+            end
+        end
+    end
+    return libs
+end
+
+function M.build(lang, conf, build_conf)
+    local target_conf = (conf.targets or {})[build_conf.target] or {}
+    local kind = build_conf.kind
+    local shared = kind == "library" and target_conf.library == "shared"
+    local out = build_conf.build_dir
+    local cc = tool(conf, "cc", "CC", "cc")
+    local cxx = tool(conf, "cxx", "CXX", "c++")
+
+    local objects, ids = {}, {}
+    local uses_cxx = lang == "cpp"
+    for _, src in ipairs(build_conf.sources) do
+        local ext = extension(src)
+        local is_cxx = CXX_EXT[ext] or false
+        if C_EXT[ext] or is_cxx then
+            uses_cxx = uses_cxx or is_cxx
+            local obj = out .. "/obj/" .. build_conf.target .. "/" .. (src:gsub("%.%.", "__")) .. ".o"
+            local cmd = { is_cxx and cxx or cc }
+            append(cmd, compile_flags(conf, target_conf, build_conf.profile, is_cxx))
+            if shared then
+                cmd[#cmd + 1] = "-fPIC"
+            end
+            append(cmd, { "-MD", "-MF", obj .. ".d", "-c", src, "-o", obj })
+            ids[#ids + 1] = liebe.task {
+                name = "cc " .. src,
+                cmd = cmd,
+                inputs = { src },
+                outputs = { obj },
+                depfile = obj .. ".d",
+                diagnostics = "gcc",
+            }
+            objects[#objects + 1] = obj
+        end
+    end
+    if #objects == 0 or kind == "custom" then
+        return
+    end
+
+    local linker = uses_cxx and cxx or cc
+    local inputs = append({}, objects)
+    local output, cmd
+    if kind == "library" and not shared then
+        output = out .. "/lib" .. build_conf.target .. ".a"
+        cmd = append({ tool(conf, "ar", "AR", "ar"), "rcs", output }, objects)
+    else
+        output = out .. "/" .. build_conf.target
+        cmd = { linker }
+        if shared then
+            output = out .. "/lib" .. build_conf.target .. ".so"
+            cmd[#cmd + 1] = "-shared"
+        end
+        append(cmd, { "-o", output })
+        append(cmd, objects)
+        for _, lib in ipairs(dep_libraries(build_conf.deps)) do
+            cmd[#cmd + 1] = lib
+            inputs[#inputs + 1] = lib
+            if extension(lib) == "so" then
+                cmd[#cmd + 1] = "-Wl,-rpath," .. lib:match("^(.*)/")
+            end
+        end
+        append(cmd, link_flags(conf, target_conf))
+    end
+    liebe.task {
+        name = "link " .. build_conf.target,
+        cmd = cmd,
+        inputs = inputs,
+        outputs = { output },
+        deps = ids,
+        diagnostics = "gcc",
+    }
+    if kind == "executable" then
+        liebe.install { files = { output }, dir = "bin", mode = "755" }
+    elseif kind == "library" then
+        liebe.install { files = { output }, dir = "lib", mode = shared and "755" or "644" }
+    end
+end
+
+function M.run(run_conf)
+    if run_conf.kind == "executable" or run_conf.kind == "test" then
+        return { run_conf.build_dir .. "/" .. run_conf.target }
+    end
+end
+
+-- Registers the `[cc]` section and the global build/run hooks
+function M.setup(lang)
+    liebe.schema("cc", schema)
+    local conf = liebe.config.cc or {}
+    function build()
+        M.build(lang, conf, build_conf)
+    end
+    function run()
+        return M.run(run_conf)
+    end
+end
+
+return M
@@ -0,0 +1,208 @@
+-- SPDX-License-Identifier: MIT
+-- Copyright (c) 2025 coppamocha
+
+--[[
+Rust support through cargo. Every target is built by a single cargo task, for
+the cargo target of the same name or the one named by `crate`: a library
+target builds the lib of the package, executables their bin. Cargo tracks
+what to rebuild by itself, liebe reruns it when the sources listed in the
+depfile cargo writes change.
+The files a build produces are known beforehand from `cargo metadata`, so
+C and C++ targets of other members can link staticlibs and cdylibs through
+`liebe.outputs`. When cargo writes them elsewhere, eg. with a `build.target`
+in its config, the tasks using them get the paths cargo reported instead.
+A staticlib usually needs `libs = ["pthread", "dl", "m"]` in the `[cc]`
+section of the members linking it.
+]]
+local strings = { type = "array", items = "string" }
+
+local target_schema = {
+    crate = "string",
+    package = "string",
+    features = strings,
+}
+
+local schema = {
+    cargo = "string",
+    manifest = "string",
+    package = "string",
+    features = strings,
+    flags = strings,
+    -- liebe profile -> cargo profile
+    profiles = { type = "map", values = "string" },
+    targets = { type = "map", values = target_schema },
+}
+
+local BUILTIN_PROFILES = { debug = "dev", release = "release" }
+
+-- Directory of the target dir cargo writes a profile to
+local PROFILE_DIRS = { dev = "debug", test = "debug", bench = "release" }
+
+local conf = liebe.config.cargo or {}
+
+local function append(list, items)
+    for _, item in ipairs(items or {}) do
+        list[#list + 1] = item
+    end
+    return list
+end
+
+local function exists(path)
+    local file = io.open(path)
+    if file then
+        file:close()
+    end
+    return file ~= nil
+end
+
+local function cargo_profile(profile)
+    local profiles = conf.profiles or {}
+    return profiles[profile.name]
+        or BUILTIN_PROFILES[profile.name]
+        or profile.inherits and (profiles[profile.inherits] or BUILTIN_PROFILES[profile.inherits])
+        or profile.name
+end
+
+local function dylib_extension()
+    return (jit and jit.os == "OSX") and "dylib" or "so"
+end
+
+local function underscored(name)
+    return (name:gsub("-", "_"))
+end
+
+local function find_package(metadata, manifest, name)
+    for _, package in ipairs(metadata.packages) do
+        if package.name == name or not name and package.manifest_path == manifest then
+            return package
+        end
+    end
+    if not name and #metadata.packages == 1 then
+        return metadata.packages[1]
+    end
+    error("no cargo package " .. (name and "`" .. name .. "`" or "") .. " in " .. manifest, 0)
+end
+
+local function has(list, value)
+    for _, item in ipairs(list) do
+        if item == value then
+            return true
+        end
+    end
+    return false
+end
+
+local function find_target(package, crate, lib)
+    for _, target in ipairs(package.targets) do
+        local is_lib = not has(target.kind, "bin") and not has(target.kind, "test")
+            and not has(target.kind, "example") and not has(target.kind, "bench")
+            and not has(target.kind, "custom-build")
+        if is_lib == lib and underscored(target.name) == underscored(crate) then
+            return target
+        end
+    end
+    error("package `" .. package.name .. "` has no " .. (lib and "lib" or "bin") .. " `" .. crate .. "`", 0)
+end
+
+-- Files cargo produces for a lib, by crate type
+local function lib_outputs(dir, target)
+    local stem = dir .. "/lib" .. underscored(target.name)
+    local outputs = {}
+    for _, crate_type in ipairs(target.crate_types) do
+        if crate_type == "staticlib" then
+            outputs[#outputs + 1] = stem .. ".a"
+        elseif crate_type == "cdylib" or crate_type == "dylib" then
+            outputs[#outputs + 1] = stem .. "." .. dylib_extension()
+        elseif crate_type == "lib" or crate_type == "rlib" then
+            outputs[#outputs + 1] = stem .. ".rlib"
+        end
+    end
+    return outputs, stem .. ".d"
+end
+
+-- Where cargo writes the artifacts of a target for the current profile
+local function profile_dir(build_dir, profile)
+    local cargo_prof = cargo_profile(profile)
+    return build_dir .. "/cargo/" .. (PROFILE_DIRS[cargo_prof] or cargo_prof), cargo_prof
+end
+
+-- The cargo target of the liebe target of `context`, build_conf or run_conf, and where it comes from
+local function locate(context)
+    local target_conf = (conf.targets or {})[context.target] or {}
+    local cargo = conf.cargo or os.getenv("CARGO") or "cargo"
+    local manifest = context.project_dir .. "/" .. (conf.manifest or "Cargo.toml")
+    local metadata = liebe.cargo_metadata(manifest, cargo)
+    local package = find_package(metadata, manifest, target_conf.package or conf.package)
+    local target = find_target(package, target_conf.crate or context.target, context.kind == "library")
+    return target, package, metadata, manifest, cargo, target_conf
+end
+
+function build()
+    local kind = build_conf.kind
+    if kind == "custom" then
+        return
+    end
+    local target, package, metadata, manifest, cargo, target_conf = locate(build_conf)
+    local lib = kind == "library"
+
+    local dir, cargo_prof = profile_dir(build_conf.build_dir, build_conf.profile)
+    local cmd = {
+        cargo, "build", "--message-format=json",
+        "--manifest-path", manifest,
+        "--target-dir", build_conf.build_dir .. "/cargo",
+        "--profile", cargo_prof,
+        "-p", package.name,
+    }
+    local outputs, depfile
+    if lib then
+        cmd[#cmd + 1] = "--lib"
+        outputs, depfile = lib_outputs(dir, target)
+    else
+        append(cmd, { "--bin", target.name })
+        outputs, depfile = { dir .. "/" .. target.name }, dir .. "/" .. target.name .. ".d"
+    end
+    local features = append(append({}, conf.features), target_conf.features)
+    if #features > 0 then
+        append(cmd, { "--features", table.concat(features, ",") })
+    end
+    append(cmd, conf.flags)
+
+    local inputs = append({ manifest }, build_conf.sources)
+    local lock = metadata.workspace_root .. "/Cargo.lock"
+    if exists(lock) then
+        inputs[#inputs + 1] = lock
+    end
+    liebe.task {
+        name = "cargo " .. target.name,
+        cmd = cmd,
+        inputs = inputs,
+        outputs = outputs,
+        depfile = depfile,
+        messages = "cargo",
+        diagnostics = "rustc",
+        pool = "cargo",
+    }
+    if lib then
+        -- rlibs are only of use to cargo
+        for _, output in ipairs(outputs) do
+            if not output:match("%.rlib$") then
+                local mode = output:match("%.a$") and "644" or "755"
+                liebe.install { files = { output }, dir = "lib", mode = mode }
+            end
+        end
+    elseif kind == "executable" then
+        liebe.install { files = outputs, dir = "bin", mode = "755" }
+    end
+end
+
+-- The bin build() wrote, named as cargo names it
+function run()
+    if run_conf.kind == "executable" or run_conf.kind == "test" then
+        local dir = profile_dir(run_conf.build_dir, run_conf.profile)
+        return { dir .. "/" .. locate(run_conf).name }
+    end
+end
+
+liebe.schema("cargo", schema)
+-- Cargo invocations sharing a target dir wait on each other's lock anyway
+liebe.pool("cargo", 1)
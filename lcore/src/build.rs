@@ -0,0 +1,219 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::error::{LiebeError, fail};
+use crate::graph::TaskGraph;
+use crate::install::{self, DEFAULT_MODE};
+use crate::luaapi::LuaApi;
+use crate::options::{OptionValues, Options};
+use crate::profile::{Profile, Profiles};
+use crate::runner::CommandStr;
+use crate::state::StateDb;
+use crate::target::Target;
+use crate::utils::did_you_mean;
+use crate::workspace::{TargetRef, Workspace};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PROFILE: &str = "debug";
+
+// What was asked for on the command line, shared by every project of the build
+#[derive(Debug, Clone, Default)]
+pub struct BuildRequest {
+    pub profile: String,
+    // `-D name=value` option overrides
+    pub defines: Vec<String>,
+    pub args: Vec<String>,
+    // Nothing is written to the build directories, eg. by `liebe compdb`
+    pub read_only: bool,
+}
+
+impl BuildRequest {
+    // A define applies to every project declaring the option, it is an error if none does
+    fn check_defines(&self, ws: &Workspace, projects: &[usize]) {
+        let declared: Vec<Options> = projects
+            .iter()
+            .map(|&id| Options::from_config(ws.projects[id].lua.config()))
+            .collect();
+        for define in &self.defines {
+            let name = define.split('=').next().unwrap_or_default();
+            if declared.iter().any(|o| o.declares(name)) {
+                continue;
+            }
+            let names = declared.iter().flat_map(Options::names);
+            let hint = did_you_mean(name, names.map(String::as_str))
+                .map(|c| format!(", did you mean `{c}`?"))
+                .unwrap_or_default();
+            fail(LiebeError::InvalidOption(format!(
+                "unknown option `{name}`{hint}"
+            )));
+        }
+    }
+
+    pub fn settings(&self, ws: &Workspace, project: usize) -> BuildSettings {
+        let config = ws.projects[project].lua.config();
+        let build_dir = ws.build_dir(project, &self.profile);
+        let options = Options::from_config(config);
+        let defines = self
+            .defines
+            .iter()
+            .filter(|d| options.declares(d.split('=').next().unwrap_or_default()))
+            .cloned()
+            .collect::<Vec<_>>();
+        BuildSettings {
+            profile: Profiles::from_config(config).resolve(&self.profile),
+            options: options.resolve(&build_dir, &defines, !self.read_only),
+            build_dir,
+            cache_dir: ws.cache_dir(),
+            args: self.args.clone(),
+        }
+    }
+}
+
+// Everything a hook gets to know about the build besides the target itself
+#[derive(Debug, Clone)]
+pub struct BuildSettings {
+    pub profile: Profile,
+    pub build_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub options: OptionValues,
+    pub args: Vec<String>,
+}
+
+impl BuildSettings {
+    // The `build_conf`/`run_conf` table handed to the hooks of a target
+    fn add_context(&self, lua: &LuaApi, name: &str, target: &Target) {
+        let context = lua.create_table();
+        let err = format!("Couldnt set value to {name}");
+        context.set("target", target.name.as_str()).expect(&err);
+        context.set("kind", target.kind.as_str()).expect(&err);
+        context
+            .set("sources", target.expand_sources(lua.dir()))
+            .expect(&err);
+        context.set("deps", target.deps.clone()).expect(&err);
+        context
+            .set("project_dir", lua.dir().display().to_string())
+            .expect(&err);
+        context.set("profile", self.profile.clone()).expect(&err);
+        context
+            .set("build_dir", self.build_dir.display().to_string())
+            .expect(&err);
+        context
+            .set("cache_dir", self.cache_dir.display().to_string())
+            .expect(&err);
+        context.set("options", self.options.clone()).expect(&err);
+        context.set("args", self.args.clone()).expect(&err);
+        lua.add_context(name, context);
+    }
+}
+
+/*
+Runs the build hooks of the requested targets (the defaults if none) and of
+everything they depend on. The graph is handed from one project's lua state
+to the next so hooks can see the outputs of targets in other members.
+*/
+pub fn generate(ws: &Workspace, requested: &[TargetRef], request: &BuildRequest) -> TaskGraph {
+    let requested = if requested.is_empty() {
+        ws.defaults()
+    } else {
+        requested.to_vec()
+    };
+    let order = ws.build_order(&requested);
+    let mut projects: Vec<usize> = order.iter().map(|r| r.project).collect();
+    projects.dedup();
+    request.check_defines(ws, &projects);
+
+    let mut settings: BTreeMap<usize, BuildSettings> = BTreeMap::new();
+    let mut graph = TaskGraph::default();
+    for r in &order {
+        let project = ws.project(r);
+        let target = project.targets.get(&r.name);
+        let deps = ws.dep_refs(r).iter().map(|d| ws.label(d)).collect();
+        graph.begin_target(&ws.label(r), deps, project.dir());
+
+        project.lua.set_graph(graph);
+        settings
+            .entry(r.project)
+            .or_insert_with(|| request.settings(ws, r.project))
+            .add_context(&project.lua, "build_conf", target);
+        project.lua.call::<()>(&target.build_hook);
+        graph = project.lua.take_graph();
+        add_installs(&mut graph, &ws.label(r), target, project.dir());
+    }
+    graph.link();
+    graph
+}
+
+// The `install-files` of a target after what its lang-script installs, or nothing with `install = false`
+fn add_installs(graph: &mut TaskGraph, label: &str, target: &Target, dir: &Path) {
+    if !target.install {
+        graph.skip_install(label);
+        return;
+    }
+    for rule in &target.install_files {
+        let mode = match &rule.mode {
+            Some(mode) => install::parse_mode(mode)
+                .unwrap_or_else(|e| fail(LiebeError::InvalidTarget(format!("{label}: {e}")))),
+            None => DEFAULT_MODE,
+        };
+        graph.add_install(target.expand(dir, &rule.files), &rule.dir, mode);
+    }
+}
+
+/*
+Calls the run hook of a target, which may return the argv of the program to
+execute. A program its task wrote somewhere else than declared runs from there.
+*/
+pub fn run_command(ws: &Workspace, r: &TargetRef, request: &BuildRequest) -> Option<CommandStr> {
+    let project = ws.project(r);
+    let target = project.targets.get(&r.name);
+    request
+        .settings(ws, r.project)
+        .add_context(&project.lua, "run_conf", target);
+    let cmd = project
+        .lua
+        .call::<Option<CommandStr>>(&target.run_hook)
+        .filter(|cmd| !cmd.is_empty())?;
+    let state = StateDb::load(&ws.cache_dir());
+    Some(
+        cmd.iter()
+            .map(|arg| state.actual(arg).to_string())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    const SCRIPT: &str = r#"
+liebe.pool("heavy", 1)
+function build()
+    liebe.task { name = "heavy " .. build_conf.target, cmd = { "true" }, pool = "heavy" }
+end
+"#;
+
+    #[test]
+    fn generate_keeps_pools_declared_by_scripts() {
+        let dir = env::temp_dir().join(format!("liebe-pools-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pool.lua"), SCRIPT).unwrap();
+        fs::write(
+            dir.join("liebe.toml"),
+            "lang-script = \"pool.lua\"\n\n[targets.app]\nkind = \"custom\"\n",
+        )
+        .unwrap();
+        let ws = Workspace::open(&dir.join("liebe.toml"));
+        let request = BuildRequest {
+            profile: DEFAULT_PROFILE.into(),
+            ..Default::default()
+        };
+        // A second generate, as on `--watch` or in the daemon, still knows the pool
+        for _ in 0..2 {
+            let graph = generate(&ws, &[], &request);
+            assert_eq!(graph.tasks.len(), 1);
+            assert_eq!(graph.pools.get("heavy"), Some(&1));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
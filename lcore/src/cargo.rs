@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::diagnostics::{self, Diagnostic};
+use crate::luaexport::json_to_lua;
+use mlua::MultiValue;
+use mlua::prelude::*;
+use std::process::Command;
+
+/*
+CARGO
+Core side of the bundled rust.lua lang-script. Cargo builds a whole crate in
+one task, run with `--message-format=json` it reports on stdout, one JSON
+object per line, the files it produced and the diagnostics of rustc.
+*/
+
+// What a cargo task printed, sorted out
+#[derive(Debug, Default)]
+pub struct Messages {
+    // Files cargo reported as built, eg. staticlibs, cdylibs and binaries
+    pub artifacts: Vec<String>,
+    // Rendered diagnostics and anything that is not a message, to be shown as is
+    pub lines: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub fn parse_messages(lines: &[String]) -> Messages {
+    let mut messages = Messages::default();
+    for line in lines {
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(line) else {
+            messages.lines.push(line.clone());
+            continue;
+        };
+        match message["reason"].as_str() {
+            Some("compiler-artifact") => {
+                let files = message["filenames"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .chain(std::iter::once(&message["executable"]));
+                for file in files.filter_map(serde_json::Value::as_str) {
+                    if !messages.artifacts.iter().any(|a| a == file) {
+                        messages.artifacts.push(file.to_string());
+                    }
+                }
+            }
+            Some("compiler-message") => {
+                if let Some(rendered) = message["message"]["rendered"].as_str() {
+                    messages.lines.push(rendered.trim_end().to_string());
+                }
+                messages
+                    .diagnostics
+                    .extend(diagnostics::from_rustc_json(&message["message"]));
+            }
+            Some(_) => {}
+            None => messages.lines.push(line.clone()),
+        }
+    }
+    messages
+}
+
+/*
+liebe.cargo_metadata(manifest, cargo?): `cargo metadata` of the packages of a
+manifest, without their dependencies, as a lua table. Lang-scripts learn from
+it the targets of a package and their crate types, to know the files a build
+produces before running it.
+*/
+pub fn lua_metadata(lua: &Lua, args: MultiValue) -> Result<MultiValue, mlua::Error> {
+    let (manifest, cargo) = <(String, Option<String>)>::from_lua_multi(args, lua)?;
+    let cargo = cargo.unwrap_or_else(|| "cargo".into());
+    let output = Command::new(&cargo)
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .args(["--manifest-path", &manifest])
+        .output()
+        .map_err(|e| {
+            mlua::Error::runtime(format!("liebe.cargo_metadata: cannot run `{cargo}`: {e}"))
+        })?;
+    if !output.status.success() {
+        return Err(mlua::Error::runtime(format!(
+            "liebe.cargo_metadata: `{cargo} metadata` failed for `{manifest}`:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| mlua::Error::runtime(format!("liebe.cargo_metadata: {e}")))?;
+    json_to_lua(lua, &metadata)?.into_lua_multi(lua)
+}
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::error::{ExitOnError, LiebeError};
+use crate::state::{StateDb, TaskRecord};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+CLEAN
+Removes the files the state database records as written by tasks, nothing
+else: sources or files put in the build dir by hand stay. Records are
+selected by the label of their target and by the directories their files
+are in, eg. the build dirs of a profile. The records of removed files are
+dropped so the tasks run again on the next build.
+*/
+#[derive(Debug, Default)]
+pub struct Selection {
+    // Target labels, all targets when empty
+    pub targets: Vec<String>,
+    // Only files inside one of these, anywhere when empty
+    pub dirs: Vec<PathBuf>,
+}
+
+impl Selection {
+    fn contains(&self, key: &str, record: &TaskRecord) -> bool {
+        let in_dirs = self.dirs.is_empty()
+            || files(key, record)
+                .iter()
+                .any(|f| self.dirs.iter().any(|d| Path::new(f).starts_with(d)));
+        in_dirs && (self.targets.is_empty() || self.targets.contains(&record.target))
+    }
+}
+
+// Records written before tasks listed what they produced only know their first output
+fn files(key: &str, record: &TaskRecord) -> Vec<String> {
+    if record.produced.is_empty() {
+        vec![key.to_string()]
+    } else {
+        record.produced.clone()
+    }
+}
+
+// Existing files the selected records produced, in a stable order
+pub fn files_to_remove(state: &StateDb, selection: &Selection) -> Vec<String> {
+    let mut out: Vec<String> = state
+        .records()
+        .filter(|(key, record)| selection.contains(key, record))
+        .flat_map(|(key, record)| files(key, record))
+        .filter(|f| Path::new(f).is_file())
+        .collect();
+    out.sort();
+    out.dedup();
+    out
+}
+
+// Directories left empty are removed too, up to `root` which always stays
+fn remove_empty_dirs(file: &Path, root: &Path) {
+    let mut dir = file.parent();
+    while let Some(d) = dir.filter(|d| d.starts_with(root) && *d != root) {
+        if fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+// Removes the files of the selected records and forgets them, returns the removed files
+pub fn clean(state: &mut StateDb, selection: &Selection, root: &Path) -> Vec<String> {
+    let removed = files_to_remove(state, selection);
+    for file in &removed {
+        fs::remove_file(file).log(LiebeError::CannotWriteFile(file));
+        remove_empty_dirs(Path::new(file), root);
+    }
+    let keys: Vec<String> = state
+        .records()
+        .filter(|(key, record)| selection.contains(key, record))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in keys {
+        state.forget_key(&key);
+    }
+    state.save();
+    removed
+}
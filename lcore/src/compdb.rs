@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::graph::TaskGraph;
+use crate::runner::TaskSpec;
+use serde_json::{Value, json};
+use std::path::Path;
+
+pub const COMPDB_FILE: &str = "compile_commands.json";
+
+// Extensions of the sources clang tooling understands
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cpp", "cxx", "c++", "C", "m", "mm", "cu", "S", "s",
+];
+
+// The source a task compiles: its first input, if that is a source file
+fn primary_input(spec: &TaskSpec) -> Option<&String> {
+    spec.inputs.first().filter(|input| {
+        Path::new(input)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| SOURCE_EXTENSIONS.contains(&e))
+    })
+}
+
+fn entry(spec: &TaskSpec, file: &str) -> Value {
+    let mut entry = json!({
+        "directory": spec.cwd.as_deref().unwrap_or("."),
+        "arguments": spec.cmd,
+        "file": file,
+    });
+    if let Some(output) = spec.outputs.first() {
+        entry["output"] = json!(output);
+    }
+    entry
+}
+
+/*
+Compilation database of the tasks compiling a source file, as read by clangd
+and clang-tidy. Only the graph is needed, nothing is built.
+*/
+pub fn generate(graph: &TaskGraph) -> String {
+    let entries = graph
+        .tasks
+        .iter()
+        .filter_map(|spec| primary_input(spec).map(|file| entry(spec, file)))
+        .collect::<Vec<_>>();
+    let mut contents = serde_json::to_string_pretty(&entries).unwrap_or_default();
+    contents.push('\n');
+    contents
+}
@@ -0,0 +1,325 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::build::{self, BuildRequest};
+use crate::error::{self, ExitOnError, LiebeError};
+use crate::graph::TaskGraph;
+use crate::runner::CommandStr;
+use crate::state::{DirtyReason, StatCache, StateDb};
+use crate::utils::json_strings;
+use crate::watch::Watcher;
+use crate::workspace::Workspace;
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+pub const SOCKET_FILE: &str = "daemon.sock";
+pub const LOG_FILE: &str = "daemon.log";
+
+const POLL: Duration = Duration::from_millis(20);
+
+/*
+DAEMON
+`liebe daemon start` keeps a workspace loaded in a background process: the
+lua states of its members, and the modification times of the sources of the
+last builds. Files are watched with inotify, a change to a liebe.toml or a
+lua script reloads the workspace, a change to a source refreshes its time.
+`build` and `run` ask the daemon for the task graph and the plan over a
+unix socket in the cache dir, and run the tasks themselves.
+An error in a build, eg. an unknown target or a failing lang-script, is sent
+back for the CLI to report, the daemon keeps running and reloads the
+workspace on the next build.
+
+Every message is a JSON object on a single line:
+    {"cmd": "build", "config": ..., "profile": ..., "defines": [...],
+     "args": [...], "targets": [...], "run": null | "" | "<target>"}
+    -> {"tasks": [...], "pools": {...}, "plan": [...], "program": [...] | null}
+     | {"error": ...}
+    {"cmd": "status"} -> {"pid": ..., "root": ..., "files": ...}
+    {"cmd": "stop"} -> {"stopped": true}
+*/
+
+pub fn socket_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(SOCKET_FILE)
+}
+
+// A build as asked for on the command line
+#[derive(Debug, Clone, Default)]
+pub struct Request {
+    // liebe.toml of the member the command runs in
+    pub config: PathBuf,
+    pub build: BuildRequest,
+    pub targets: Vec<String>,
+    // Target to get the program of on `run`, empty for the default one
+    pub run: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Reply {
+    pub graph: TaskGraph,
+    pub plan: Vec<Option<DirtyReason>>,
+    pub program: Option<CommandStr>,
+}
+
+// Sends one message to the daemon of a workspace and waits for the answer
+fn call(cache_dir: &Path, message: &Value) -> Option<Value> {
+    let socket = socket_path(cache_dir);
+    let mut stream = match UnixStream::connect(&socket) {
+        Ok(stream) => stream,
+        // Left over from a daemon that ended without cleaning up
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            let _ = fs::remove_file(&socket);
+            return None;
+        }
+        Err(_) => return None,
+    };
+    writeln!(stream, "{message}").ok()?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).ok()?;
+    serde_json::from_str(&line).ok()
+}
+
+/*
+Graph and plan of a build from the daemon, or the error the build failed
+with. `None` if no daemon runs or it did not answer.
+*/
+pub fn request(cache_dir: &Path, request: &Request) -> Option<Result<Reply, String>> {
+    let message = json!({
+        "cmd": "build",
+        "config": request.config,
+        "profile": request.build.profile,
+        "defines": request.build.defines,
+        "args": request.build.args,
+        "targets": request.targets,
+        "run": request.run,
+    });
+    let reply = call(cache_dir, &message)?;
+    if let Some(error) = reply["error"].as_str() {
+        return Some(Err(error.to_string()));
+    }
+    reply["tasks"].as_array()?;
+    Some(Ok(Reply {
+        graph: TaskGraph::from_json(&reply),
+        plan: reply["plan"]
+            .as_array()?
+            .iter()
+            .map(DirtyReason::from_json)
+            .collect(),
+        program: reply["program"]
+            .as_array()
+            .map(|_| json_strings(&reply["program"])),
+    }))
+}
+
+pub fn status(cache_dir: &Path) -> Option<Value> {
+    call(cache_dir, &json!({ "cmd": "status" }))
+}
+
+pub fn stop(cache_dir: &Path) -> bool {
+    call(cache_dir, &json!({ "cmd": "stop" })).is_some()
+}
+
+struct Daemon {
+    ws: Workspace,
+    config: PathBuf,
+    watcher: Watcher,
+    stats: Arc<Mutex<StatCache>>,
+    // liebe.toml files and lua scripts, a change to one of them reloads the workspace
+    sources: Vec<PathBuf>,
+    stale: bool,
+}
+
+impl Daemon {
+    fn new(config: &Path) -> Self {
+        let mut daemon = Self {
+            ws: Workspace::open(config),
+            config: config.to_path_buf(),
+            watcher: Watcher::new(),
+            stats: Arc::default(),
+            sources: Vec::new(),
+            stale: false,
+        };
+        daemon.watch_sources();
+        daemon
+    }
+
+    fn watch_sources(&mut self) {
+        self.sources = self.ws.config_files();
+        self.sources.extend(self.ws.script_files());
+        self.watcher.watch(&self.sources);
+    }
+
+    fn apply_changes(&mut self) {
+        let changed = self.watcher.poll();
+        let mut stats = self
+            .stats
+            .lock()
+            .log(LiebeError::CannotWatchFiles("stat cache"));
+        for path in changed {
+            if self.sources.contains(&path) {
+                println!("Changed: {}, reloading", path.display());
+                self.stale = true;
+            } else {
+                stats.refresh(&path.display().to_string());
+            }
+        }
+    }
+
+    // Files are watched before their time is read, so no change can go unnoticed
+    fn track(&mut self, files: Vec<String>) {
+        let mut stats = self
+            .stats
+            .lock()
+            .log(LiebeError::CannotWatchFiles("stat cache"));
+        let new: Vec<String> = files.into_iter().filter(|f| !stats.contains(f)).collect();
+        self.watcher.watch(&new);
+        for file in &new {
+            stats.refresh(file);
+        }
+    }
+
+    fn build(&mut self, message: &Value) -> Value {
+        if self.stale {
+            self.ws = Workspace::open(&self.config);
+            self.watch_sources();
+            self.stale = false;
+        }
+        let config = PathBuf::from(message["config"].as_str().unwrap_or_default());
+        self.ws
+            .set_current(config.parent().unwrap_or(Path::new("")));
+        let request = BuildRequest {
+            profile: message["profile"].as_str().unwrap_or_default().to_string(),
+            defines: json_strings(&message["defines"]),
+            args: json_strings(&message["args"]),
+            read_only: false,
+        };
+        let targets: Vec<_> = json_strings(&message["targets"])
+            .iter()
+            .flat_map(|t| self.ws.resolve_many(t))
+            .collect();
+        let program = message["run"].as_str().map(|name| {
+            let r = self.ws.runnable((!name.is_empty()).then_some(name));
+            (
+                build::generate(&self.ws, std::slice::from_ref(&r), &request),
+                r,
+            )
+        });
+        let (graph, program) = match program {
+            Some((graph, r)) => (graph, build::run_command(&self.ws, &r, &request)),
+            None => (build::generate(&self.ws, &targets, &request), None),
+        };
+        // Scripts the build hooks required for the first time
+        for script in self.ws.script_files() {
+            if !self.sources.contains(&script) {
+                self.watcher.watch([&script]);
+                self.sources.push(script);
+            }
+        }
+
+        let state = StateDb::load(&self.ws.cache_dir()).with_stats(self.stats.clone());
+        self.track(graph.source_files(&state));
+        let plan = graph.clone().into_runner().with_state(state).plan();
+        let mut reply = graph.to_json();
+        reply["plan"] = plan
+            .iter()
+            .map(|r| r.as_ref().map_or(Value::Null, DirtyReason::to_json))
+            .collect();
+        reply["program"] = json!(program);
+        reply
+    }
+
+    // Errors of a build are answered, what they left half done is reloaded next time
+    fn try_build(&mut self, message: &Value) -> Value {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.build(message))) {
+            Ok(reply) => reply,
+            Err(payload) => {
+                self.stale = true;
+                let error = payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_else(|| "the daemon failed to build".into());
+                json!({ "error": error })
+            }
+        }
+    }
+
+    fn status(&self) -> Value {
+        let files = self.stats.lock().map(|s| s.len()).unwrap_or_default();
+        json!({
+            "pid": process::id(),
+            "root": self.ws.root,
+            "files": files,
+        })
+    }
+
+    // Answers one message, false once asked to stop
+    fn handle(&mut self, stream: UnixStream) -> bool {
+        let mut line = String::new();
+        let Ok(mut reader) = stream.try_clone().map(BufReader::new) else {
+            return true;
+        };
+        if reader.read_line(&mut line).is_err() {
+            return true;
+        }
+        let message: Value = serde_json::from_str(&line).unwrap_or_default();
+        self.apply_changes();
+        let (reply, running) = match message["cmd"].as_str() {
+            Some("build") => (self.try_build(&message), true),
+            Some("status") => (self.status(), true),
+            Some("stop") => (json!({ "stopped": true }), false),
+            _ => (json!({ "error": "unknown command" }), true),
+        };
+        let mut stream = stream;
+        let _ = writeln!(stream, "{reply}");
+        running
+    }
+}
+
+// Serves the workspace of `config` until asked to stop
+pub fn serve(config: &Path) {
+    let mut daemon = Daemon::new(config);
+    error::set_recoverable(true);
+    let socket = socket_path(&daemon.ws.cache_dir());
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir).log(LiebeError::CannotWriteFile(dir.display().to_string()));
+    }
+    // A socket nobody answers on is left over from a daemon that did not stop cleanly
+    if UnixStream::connect(&socket).is_ok() {
+        println!("A daemon already serves {}", daemon.ws.root.display());
+        return;
+    }
+    let _ = fs::remove_file(&socket);
+    let listener =
+        UnixListener::bind(&socket).log(LiebeError::CannotWriteFile(socket.display().to_string()));
+    listener
+        .set_nonblocking(true)
+        .log(LiebeError::CannotWriteFile(socket.display().to_string()));
+    println!(
+        "Serving {} on {}",
+        daemon.ws.root.display(),
+        socket.display()
+    );
+
+    loop {
+        daemon.apply_changes();
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream.set_nonblocking(false);
+                if !daemon.handle(stream) {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => sleep(POLL),
+            Err(_) => break,
+        }
+    }
+    let _ = fs::remove_file(&socket);
+    println!("Stopped");
+}
@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use std::path::Path;
+
+// Line MSVC prints on stdout for every header when compiling with /showIncludes
+pub const MSVC_INCLUDE_PREFIX: &str = "Note: including file:";
+
+// How a task reports the inputs it discovered while running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepStyle {
+    // Makefile rules written to a file, as with `gcc -MD -MF <file>`
+    Make(String),
+    // `cl /showIncludes` notes on stdout
+    Msvc,
+}
+
+// Splits a line of a Makefile rule on unescaped whitespace
+fn tokens(line: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut token = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some(' ' | '#')) => {
+                token.push(chars.next().unwrap_or_default());
+            }
+            '$' if chars.peek() == Some(&'$') => {
+                chars.next();
+                token.push('$');
+            }
+            c if c.is_whitespace() => {
+                if !token.is_empty() {
+                    out.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        out.push(token);
+    }
+    out
+}
+
+/*
+Prerequisites of all the rules of a depfile. `-MP` adds an empty rule for
+every header, those only repeat prerequisites already listed.
+*/
+pub fn parse_make(contents: &str) -> Vec<String> {
+    let joined = contents.replace("\\\r\n", " ").replace("\\\n", " ");
+    let mut deps: Vec<String> = Vec::new();
+    for line in joined.lines() {
+        let tokens = tokens(line);
+        let Some(colon) = tokens.iter().position(|t| t.ends_with(':')) else {
+            continue;
+        };
+        for dep in &tokens[colon + 1..] {
+            if !deps.contains(dep) {
+                deps.push(dep.clone());
+            }
+        }
+    }
+    deps
+}
+
+// Escapes a path as a target or prerequisite of a rule
+fn escape(path: &str) -> String {
+    path.replace('$', "$$")
+        .replace(' ', "\\ ")
+        .replace('#', "\\#")
+}
+
+/*
+The depfile with its relative paths taken from `dir`. Compilers write paths
+as they were given, relative to the directory of the task, while the build
+tools of `liebe gen` read depfiles from the directory they run in.
+*/
+pub fn absolute_make(contents: &str, dir: &Path) -> String {
+    let joined = contents.replace("\\\r\n", " ").replace("\\\n", " ");
+    let mut out = String::new();
+    for line in joined.lines() {
+        let tokens: Vec<String> = tokens(line)
+            .iter()
+            .map(|token| {
+                let (path, colon) = match token.strip_suffix(':') {
+                    Some(path) => (path, ":"),
+                    None => (token.as_str(), ""),
+                };
+                format!("{}{colon}", escape(&dir.join(path).display().to_string()))
+            })
+            .collect();
+        if !tokens.is_empty() {
+            out.push_str(&tokens.join(" "));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// Splits /showIncludes output into the included files and the remaining lines
+pub fn parse_msvc(output: &str) -> (Vec<String>, Vec<&str>) {
+    let mut deps: Vec<String> = Vec::new();
+    let mut rest = Vec::new();
+    for line in output.lines() {
+        match line.strip_prefix(MSVC_INCLUDE_PREFIX) {
+            Some(path) => {
+                let path = path.trim().to_string();
+                if !deps.contains(&path) {
+                    deps.push(path);
+                }
+            }
+            None => rest.push(line),
+        }
+    }
+    (deps, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_make_joins_continuations_and_unescapes() {
+        let contents = "main.o: src/main.c \\\n include/my\\ file.h \\\r\n  lib/$$x.h\n";
+        assert_eq!(
+            parse_make(contents),
+            ["src/main.c", "include/my file.h", "lib/$x.h"]
+        );
+    }
+
+    #[test]
+    fn parse_make_skips_repeated_prerequisites() {
+        // As written with -MP, the empty rules name the headers again
+        let contents = "a.o: a.c a.h b.h\na.h:\n\nb.h:\nb.o: b.c a.h\n";
+        assert_eq!(parse_make(contents), ["a.c", "a.h", "b.h", "b.c"]);
+    }
+
+    #[test]
+    fn parse_make_keeps_windows_paths() {
+        let contents = "C:\\out\\a.obj: C:\\src\\a.c \\\r\n  C:\\src\\a.h\r\n";
+        assert_eq!(parse_make(contents), ["C:\\src\\a.c", "C:\\src\\a.h"]);
+    }
+
+    #[test]
+    fn parse_msvc_separates_includes_from_output() {
+        let output = "a.c\r\nNote: including file: C:\\inc\\a.h\r\n\
+                      Note: including file:  C:\\inc\\b.h\r\n\
+                      Note: including file: C:\\inc\\a.h\r\n\
+                      a.c(3): warning C4101: unused\r\n";
+        let (deps, rest) = parse_msvc(output);
+        assert_eq!(deps, ["C:\\inc\\a.h", "C:\\inc\\b.h"]);
+        assert_eq!(rest, ["a.c", "a.c(3): warning C4101: unused"]);
+    }
+
+    #[test]
+    fn absolute_make_takes_relative_paths_from_dir() {
+        let contents = "/out/main.o: src/main.c \\\n  include/my\\ file.h /usr/include/stdio.h\ninclude/a.h:\n";
+        assert_eq!(
+            absolute_make(contents, Path::new("/ws/app")),
+            "/out/main.o: /ws/app/src/main.c /ws/app/include/my\\ file.h /usr/include/stdio.h\n\
+             /ws/app/include/a.h:\n"
+        );
+    }
+}
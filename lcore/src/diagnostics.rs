@@ -0,0 +1,493 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::runner::Task;
+use serde_json::{Value, json};
+use std::fmt::{self, Display};
+
+/*
+DIAGNOSTICS
+Tasks declaring `diagnostics = "gcc" | "rustc"` have the errors and warnings
+of the compiler read from their stderr, or from the messages of cargo. They
+are still printed as the compiler wrote them, the build then ends with how
+many there were and the first error. The same header compiled by several
+tasks warns once for every one of them, so diagnostics are counted once.
+Notes and help are part of the diagnostic they follow and are not counted.
+*/
+
+// Format of the diagnostics a task prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticFormat {
+    // `file:line:col: severity: message`, as GCC and Clang print them
+    Gcc,
+    // JSON of `rustc --error-format=json`, or of the compiler messages of cargo
+    Rustc,
+}
+
+impl DiagnosticFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "gcc" => Some(Self::Gcc),
+            "rustc" => Some(Self::Rustc),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gcc => "gcc",
+            Self::Rustc => "rustc",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    // Without a file, the diagnostic is about the whole invocation, eg. `collect2: error: ...`
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+            for n in [self.line, self.column].into_iter().flatten() {
+                write!(f, "{n}:")?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+impl Diagnostic {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "severity": self.severity.to_string(),
+            "message": self.message,
+            "file": self.file,
+            "line": self.line,
+            "column": self.column,
+        })
+    }
+}
+
+// Line and column at the end of `file:line:col`, as far as they are numbers
+fn location(location: &str) -> (&str, Option<u32>, Option<u32>) {
+    let mut file = location;
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        match file.rsplit_once(':') {
+            Some((rest, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => {
+                numbers.insert(0, n.parse().ok());
+                file = rest;
+            }
+            _ => break,
+        }
+    }
+    let mut numbers = numbers.into_iter().flatten();
+    (file, numbers.next(), numbers.next())
+}
+
+// A line of GCC or Clang starting a diagnostic, `fatal error` counts as an error
+pub fn parse_gcc_line(line: &str) -> Option<Diagnostic> {
+    let severities = [
+        (": fatal error: ", Severity::Error),
+        (": error: ", Severity::Error),
+        (": warning: ", Severity::Warning),
+    ];
+    let (at, marker, severity) = severities
+        .iter()
+        .filter_map(|(marker, severity)| Some((line.find(marker)?, marker, *severity)))
+        .min_by_key(|(at, _, _)| *at)?;
+    let (file, line_no, column) = location(&line[..at]);
+    if file.is_empty() || file.contains(' ') && line_no.is_none() {
+        return None;
+    }
+    let message = line[at + marker.len()..].trim().to_string();
+    // A tool rather than a file, eg. `collect2: error: ld returned 1 exit status`
+    if line_no.is_none() {
+        return Some(Diagnostic {
+            severity,
+            message: format!("{file}: {message}"),
+            file: None,
+            line: None,
+            column: None,
+        });
+    }
+    Some(Diagnostic {
+        severity,
+        message,
+        file: Some(file.to_string()),
+        line: line_no,
+        column,
+    })
+}
+
+pub fn parse_gcc(lines: &[String]) -> Vec<Diagnostic> {
+    lines.iter().filter_map(|l| parse_gcc_line(l)).collect()
+}
+
+/*
+A diagnostic of rustc as JSON, located at its primary span. The closing
+`aborting due to N previous errors` and `N warnings emitted` only count the
+others and are left out.
+*/
+pub fn from_rustc_json(message: &Value) -> Option<Diagnostic> {
+    let severity = match message["level"].as_str()? {
+        "error" | "error: internal compiler error" => Severity::Error,
+        "warning" => Severity::Warning,
+        _ => return None,
+    };
+    let text = message["message"].as_str()?;
+    let span = message["spans"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|s| s["is_primary"].as_bool() == Some(true));
+    if span.is_none() && (text.starts_with("aborting due to") || text.ends_with("emitted")) {
+        return None;
+    }
+    let number = |key: &str| span.and_then(|s| s[key].as_u64()).map(|n| n as u32);
+    let message = match message["code"]["code"].as_str() {
+        Some(code) => format!("{text} [{code}]"),
+        None => text.to_string(),
+    };
+    Some(Diagnostic {
+        severity,
+        message,
+        file: span.and_then(|s| s["file_name"].as_str()).map(String::from),
+        line: number("line_start"),
+        column: number("column_start"),
+    })
+}
+
+/*
+Lines rustc printed on stderr with `--error-format=json`: the diagnostics,
+and the lines to show instead, their rendered text in place of the JSON.
+*/
+pub fn parse_rustc(lines: Vec<String>) -> (Vec<Diagnostic>, Vec<String>) {
+    let mut diagnostics = Vec::new();
+    let mut rest = Vec::new();
+    for line in lines {
+        let message = serde_json::from_str::<Value>(&line)
+            .ok()
+            .filter(|m| m["$message_type"].as_str() == Some("diagnostic"));
+        let Some(message) = message else {
+            rest.push(line);
+            continue;
+        };
+        if let Some(rendered) = message["rendered"].as_str() {
+            rest.push(rendered.trim_end().to_string());
+        }
+        diagnostics.extend(from_rustc_json(&message));
+    }
+    (diagnostics, rest)
+}
+
+// The diagnostics of a build, each counted once
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Summary {
+    pub fn of(tasks: &[Task]) -> Self {
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        for diagnostic in tasks.iter().flat_map(Task::diagnostics) {
+            if !diagnostics.contains(diagnostic) {
+                diagnostics.push(diagnostic.clone());
+            }
+        }
+        Self { diagnostics }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    pub fn first_error(&self) -> Option<&Diagnostic> {
+        self.diagnostics
+            .iter()
+            .find(|d| d.severity == Severity::Error)
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: usize, word: &str| match n {
+            1 => format!("1 {word}"),
+            n => format!("{n} {word}s"),
+        };
+        write!(
+            f,
+            "{}, {}",
+            plural(self.errors(), "error"),
+            plural(self.warnings(), "warning")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::TaskSpec;
+    use std::env;
+
+    fn diagnostic(
+        severity: Severity,
+        message: &str,
+        at: Option<(&str, u32, Option<u32>)>,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            file: at.map(|(file, _, _)| file.into()),
+            line: at.map(|(_, line, _)| line),
+            column: at.and_then(|(_, _, column)| column),
+        }
+    }
+
+    #[test]
+    fn location_takes_numbers_from_the_end() {
+        assert_eq!(location("src/a.c:3:14"), ("src/a.c", Some(3), Some(14)));
+        assert_eq!(location("src/a.c:3"), ("src/a.c", Some(3), None));
+        assert_eq!(location("src/a.c"), ("src/a.c", None, None));
+        assert_eq!(
+            location("C:\\src\\a.c:3:4"),
+            ("C:\\src\\a.c", Some(3), Some(4))
+        );
+        assert_eq!(location("a:1:b.c:2:3"), ("a:1:b.c", Some(2), Some(3)));
+    }
+
+    #[test]
+    fn gcc_lines() {
+        assert_eq!(
+            parse_gcc_line("src/a.c:3:14: error: expected ';' before '}' token"),
+            Some(diagnostic(
+                Severity::Error,
+                "expected ';' before '}' token",
+                Some(("src/a.c", 3, Some(14)))
+            ))
+        );
+        assert_eq!(
+            parse_gcc_line("src/a.c:7: warning: unused variable 'x' [-Wunused-variable]"),
+            Some(diagnostic(
+                Severity::Warning,
+                "unused variable 'x' [-Wunused-variable]",
+                Some(("src/a.c", 7, None))
+            ))
+        );
+        assert_eq!(
+            parse_gcc_line("src/a.c:1:10: fatal error: b.h: No such file or directory"),
+            Some(diagnostic(
+                Severity::Error,
+                "b.h: No such file or directory",
+                Some(("src/a.c", 1, Some(10)))
+            ))
+        );
+        assert_eq!(
+            parse_gcc_line("C:\\src\\a.c:3:4: error: x"),
+            Some(diagnostic(
+                Severity::Error,
+                "x",
+                Some(("C:\\src\\a.c", 3, Some(4)))
+            ))
+        );
+    }
+
+    #[test]
+    fn gcc_tool_diagnostics_have_no_file() {
+        assert_eq!(
+            parse_gcc_line("collect2: error: ld returned 1 exit status"),
+            Some(diagnostic(
+                Severity::Error,
+                "collect2: ld returned 1 exit status",
+                None
+            ))
+        );
+        assert_eq!(
+            parse_gcc_line("cc1: fatal error: src/x.c: No such file or directory"),
+            Some(diagnostic(
+                Severity::Error,
+                "cc1: src/x.c: No such file or directory",
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn gcc_other_lines_are_not_diagnostics() {
+        for line in [
+            "In file included from src/a.c:1:",
+            "src/a.c: In function 'main':",
+            "src/a.c:3:14: note: declared here",
+            "    3 |   int x",
+            "the expected output: error: none",
+            "",
+        ] {
+            assert_eq!(parse_gcc_line(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn gcc_first_severity_wins() {
+        let d = parse_gcc_line("a.c:1:2: warning: format: error: in string").unwrap();
+        assert_eq!(d.severity, Severity::Warning);
+        assert_eq!(d.message, "format: error: in string");
+    }
+
+    #[test]
+    fn rustc_json_at_the_primary_span() {
+        let message = json!({
+            "$message_type": "diagnostic",
+            "level": "error",
+            "message": "mismatched types",
+            "code": { "code": "E0308" },
+            "spans": [
+                { "file_name": "src/lib.rs", "line_start": 2, "column_start": 1, "is_primary": false },
+                { "file_name": "src/main.rs", "line_start": 4, "column_start": 18, "is_primary": true },
+            ],
+            "rendered": "error[E0308]: mismatched types\n",
+        });
+        assert_eq!(
+            from_rustc_json(&message),
+            Some(diagnostic(
+                Severity::Error,
+                "mismatched types [E0308]",
+                Some(("src/main.rs", 4, Some(18)))
+            ))
+        );
+    }
+
+    #[test]
+    fn rustc_json_skips_notes_and_totals() {
+        let level = |level: &str, message: &str| json!({ "level": level, "message": message, "code": null, "spans": [] });
+        assert_eq!(
+            from_rustc_json(&level("note", "for more information")),
+            None
+        );
+        assert_eq!(
+            from_rustc_json(&level("error", "aborting due to 2 previous errors")),
+            None
+        );
+        assert_eq!(
+            from_rustc_json(&level("warning", "3 warnings emitted")),
+            None
+        );
+        assert_eq!(
+            from_rustc_json(&level("error", "linking with `cc` failed")),
+            Some(diagnostic(
+                Severity::Error,
+                "linking with `cc` failed",
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn rustc_lines_are_rendered() {
+        let warning = json!({
+            "$message_type": "diagnostic",
+            "level": "warning",
+            "message": "unused variable: `x`",
+            "code": { "code": "unused_variables" },
+            "spans": [{ "file_name": "src/main.rs", "line_start": 2, "column_start": 9, "is_primary": true }],
+            "rendered": "warning: unused variable: `x`\n --> src/main.rs:2:9\n\n",
+        });
+        let artifact = json!({ "$message_type": "artifact", "artifact": "main.d" });
+        let lines = vec![
+            warning.to_string(),
+            artifact.to_string(),
+            "thread 'rustc' panicked".to_string(),
+        ];
+        let (diagnostics, rest) = parse_rustc(lines);
+        assert_eq!(
+            diagnostics,
+            [diagnostic(
+                Severity::Warning,
+                "unused variable: `x` [unused_variables]",
+                Some(("src/main.rs", 2, Some(9)))
+            )]
+        );
+        assert_eq!(
+            rest,
+            [
+                "warning: unused variable: `x`\n --> src/main.rs:2:9".to_string(),
+                artifact.to_string(),
+                "thread 'rustc' panicked".to_string(),
+            ]
+        );
+    }
+
+    // A compile task printing `stderr`, run to completion
+    fn compile(stderr: &str) -> Task {
+        let mut task = Task::from_spec(TaskSpec {
+            cmd: vec!["sh".into(), "-c".into(), format!("printf '{stderr}' >&2")],
+            cwd: Some(env::temp_dir().display().to_string()),
+            diagnostics: Some(DiagnosticFormat::Gcc),
+            ..Default::default()
+        });
+        task.run();
+        task.wait();
+        task
+    }
+
+    #[test]
+    fn summary_counts_each_diagnostic_once() {
+        let header = "inc/a.h:2:5: warning: unused function\\n";
+        let tasks = [
+            compile(&format!("{header}src/a.c:4:1: error: expected ;\\n")),
+            compile(&format!(
+                "{header}src/b.c:9:3: warning: shadowed\\ncollect2: error: ld failed\\n"
+            )),
+        ];
+        let summary = Summary::of(&tasks);
+        let file = env::temp_dir().join("src/a.c").display().to_string();
+        assert_eq!(summary.errors(), 2);
+        assert_eq!(summary.warnings(), 2);
+        assert_eq!(summary.to_string(), "2 errors, 2 warnings");
+        assert_eq!(
+            summary.first_error(),
+            Some(&diagnostic(
+                Severity::Error,
+                "expected ;",
+                Some((&file, 4, Some(1)))
+            ))
+        );
+        assert_eq!(Summary::of(&tasks[..0]).to_string(), "0 errors, 0 warnings");
+    }
+}
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::graph::TaskGraph;
+use crate::workspace::{TargetRef, Workspace};
+use std::fmt::Write;
+
+/*
+Graphviz output of `liebe graph --dot`. An edge goes from a target or a task
+to what it depends on, order-only dependencies of tasks are dashed. Tasks
+are grouped in a cluster per target.
+*/
+
+// `s` as a quoted DOT identifier, line breaks become the `\n` escape of labels
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+pub fn targets(ws: &Workspace, order: &[TargetRef]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph targets {{");
+    let _ = writeln!(out, "  node [shape=box];");
+    for r in order {
+        let target = ws.project(r).targets.get(&r.name);
+        let label = ws.label(r);
+        let _ = writeln!(
+            out,
+            "  {} [label={}];",
+            quote(&label),
+            quote(&format!("{label}\n{}", target.kind.as_str()))
+        );
+        for dep in ws.dep_refs(r) {
+            let _ = writeln!(out, "  {} -> {};", quote(&label), quote(&ws.label(&dep)));
+        }
+    }
+    let _ = writeln!(out, "}}");
+    out
+}
+
+pub fn tasks(graph: &TaskGraph) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph tasks {{");
+    let _ = writeln!(out, "  node [shape=box];");
+    for (cluster, (label, ids)) in graph.targets().into_iter().enumerate() {
+        let _ = writeln!(out, "  subgraph cluster_{cluster} {{");
+        let _ = writeln!(out, "    label={};", quote(label));
+        for id in ids {
+            let _ = writeln!(out, "    t{id} [label={}];", quote(&graph.tasks[id].name));
+        }
+        let _ = writeln!(out, "  }}");
+    }
+    for (id, spec) in graph.tasks.iter().enumerate() {
+        for dep in &spec.deps {
+            let _ = writeln!(out, "  t{id} -> t{dep};");
+        }
+        for dep in &spec.order_deps {
+            let _ = writeln!(out, "  t{id} -> t{dep} [style=dashed];");
+        }
+    }
+    let _ = writeln!(out, "}}");
+    out
+}
@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 coppamocha
 use std::fmt::{Debug, Display};
+use std::panic;
 use std::process::exit;
 use thiserror::Error;
 
@@ -11,7 +12,23 @@ pub fn set_verbose(val: bool) {
 }
 
 pub fn get_verbose() -> bool {
-    unsafe { return VERBOSE }
+    unsafe { VERBOSE }
+}
+
+// Set by the daemon, which catches errors to answer them instead of exiting
+pub static mut RECOVERABLE: bool = false;
+
+pub fn set_recoverable(val: bool) {
+    unsafe { RECOVERABLE = val }
+}
+
+// Prints an error and exits, or unwinds with it as the payload when recoverable
+fn exit_with(message: String) -> ! {
+    eprintln!("{message}");
+    if unsafe { RECOVERABLE } {
+        panic::resume_unwind(Box::new(message));
+    }
+    exit(1);
 }
 
 pub trait ExitOnError<T, Q>
@@ -29,15 +46,16 @@ where
     Q: AsRef<str> + Display,
 {
     fn log(self, e: LiebeError<Q>) -> T {
-        if self.is_err() {
-            if unsafe { VERBOSE } {
-                eprintln!("{}: {:#?}", e.as_pretty(), self.unwrap_err());
-            } else {
-                eprintln!("{}", e.as_pretty());
+        match self {
+            Ok(val) => val,
+            Err(err) => {
+                if unsafe { VERBOSE } {
+                    exit_with(format!("{}: {:#?}", e.as_pretty(), err));
+                } else {
+                    exit_with(e.as_pretty());
+                }
             }
-            exit(1);
         }
-        self.unwrap()
     }
 }
 
@@ -48,13 +66,17 @@ where
 {
     fn log(self, e: LiebeError<Q>) -> T {
         if self.is_none() {
-            eprintln!("{}", e.as_pretty());
-            exit(1);
+            exit_with(e.as_pretty());
         }
         self.unwrap()
     }
 }
 
+// Reports an error that has no underlying Result/Option and exits
+pub fn fail<Q: AsRef<str> + Display>(e: LiebeError<Q>) -> ! {
+    exit_with(e.as_pretty());
+}
+
 #[macro_export]
 macro_rules! empty_err {
     ($variant: ident) => {
@@ -73,6 +95,8 @@ where
     CannotOpenFile(T),
     #[error("Cannot read from file")]
     CannotReadFile(T),
+    #[error("Cannot write to file")]
+    CannotWriteFile(T),
     #[error("Invalid configuration file")]
     InvalidConf,
     #[error("Cannot open lua stdlibs")]
@@ -85,8 +109,30 @@ where
     FuncNotFound(T),
     #[error("Cannot call lua function")]
     CannotCallFunc(T),
+    #[error("Error in lang-script")]
+    LangScriptError(T),
     #[error("Cannot find a field in configuration")]
     CantFindFieldInConf(T),
+    #[error("Configuration does not match the schema")]
+    InvalidSchema(T),
+    #[error("Unknown profile")]
+    UnknownProfile(T),
+    #[error("Invalid profile")]
+    InvalidProfile(T),
+    #[error("Invalid build option")]
+    InvalidOption(T),
+    #[error("Unknown target")]
+    UnknownTarget(T),
+    #[error("Invalid target")]
+    InvalidTarget(T),
+    #[error("Unknown task")]
+    UnknownTask(T),
+    #[error("Invalid workspace")]
+    InvalidWorkspace(T),
+    #[error("Cannot watch files")]
+    CannotWatchFiles(T),
+    #[error("Daemon error")]
+    DaemonError(T),
     #[error("Thread failed to join")]
     ThreadFailedToJoin,
     #[error("Thread already has been joined")]
@@ -98,10 +144,22 @@ impl<T: AsRef<str> + Display> LiebeError<T> {
         match self {
             Self::CannotOpenFile(str) => format!("{self}: {str}"),
             Self::CannotReadFile(str) => format!("{self}: {str}"),
+            Self::CannotWriteFile(str) => format!("{self}: {str}"),
             Self::CannotInjectContext(str) => format!("{self}: {str}"),
             Self::FuncNotFound(str) => format!("{self}: {str}"),
             Self::CannotCallFunc(str) => format!("{self}: {str}"),
+            Self::LangScriptError(str) => format!("{self}:\n{str}"),
             Self::CantFindFieldInConf(str) => format!("{self}: {str}"),
+            Self::InvalidSchema(str) => format!("{self}:\n{str}"),
+            Self::UnknownProfile(str) => format!("{self}: {str}"),
+            Self::InvalidProfile(str) => format!("{self}: {str}"),
+            Self::InvalidOption(str) => format!("{self}: {str}"),
+            Self::UnknownTarget(str) => format!("{self}: {str}"),
+            Self::InvalidTarget(str) => format!("{self}: {str}"),
+            Self::UnknownTask(str) => format!("{self}: {str}"),
+            Self::InvalidWorkspace(str) => format!("{self}: {str}"),
+            Self::CannotWatchFiles(str) => format!("{self}: {str}"),
+            Self::DaemonError(str) => format!("{self}: {str}"),
             _ => self.to_string(),
         }
     }
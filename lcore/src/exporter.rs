@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::depfile::DepStyle;
+use crate::graph::TaskGraph;
+use crate::runner::TaskSpec;
+use crate::utils::shell_quote;
+use std::env;
+
+/*
+EXPORTERS
+Helpers shared by the backends translating the task graph into files for
+another build tool, like `liebe gen ninja`. The exported files run the same
+commands as the native runner, through a shell.
+*/
+
+// How to rerun liebe when the files the graph was generated from change
+#[derive(Debug, Clone, Default)]
+pub struct Regen {
+    // Directory liebe was started from
+    pub cwd: String,
+    pub argv: Vec<String>,
+    // liebe.toml files of the workspace
+    pub inputs: Vec<String>,
+}
+
+impl Regen {
+    pub fn command_line(&self) -> String {
+        format!("cd {} && {}", shell_quote(&self.cwd), quoted(&self.argv))
+    }
+}
+
+fn quoted(args: &[String]) -> String {
+    args.iter()
+        .map(|a| shell_quote(a))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// The command of a task as a shell command line, run from its directory with its environment
+pub fn command_line(spec: &TaskSpec) -> String {
+    let mut line = String::new();
+    if let Some(cwd) = &spec.cwd {
+        line.push_str(&format!("cd {} && ", shell_quote(cwd)));
+    }
+    if !spec.env.is_empty() {
+        let env = spec
+            .env
+            .iter()
+            .map(|(k, v)| shell_quote(&format!("{k}={v}")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        line.push_str(&format!("env {env} "));
+    }
+    line.push_str(&quoted(&spec.cmd));
+    // The paths of the depfile are relative to `cwd`, the build tool reads them from its own directory
+    if let (Some(_), Some(DepStyle::Make(depfile))) = (&spec.cwd, &spec.depfile) {
+        let liebe = env::current_exe()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "liebe".into());
+        line.push_str(&format!(
+            " && {} depfile {}",
+            shell_quote(&liebe),
+            shell_quote(depfile)
+        ));
+    }
+    line
+}
+
+/*
+Files standing for a task in the exported graph. A task without outputs gets
+a name no file is created under, so it runs every time like in the runner.
+*/
+pub fn task_outputs(spec: &TaskSpec, id: usize) -> Vec<String> {
+    if spec.outputs.is_empty() {
+        vec![format!("liebe-task-{id}")]
+    } else {
+        spec.outputs.clone()
+    }
+}
+
+// Outputs of the tasks at `ids` that are not already listed in `exclude`
+pub fn outputs_of(graph: &TaskGraph, ids: &[usize], exclude: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for &id in ids {
+        for output in task_outputs(&graph.tasks[id], id) {
+            if !exclude.contains(&output) && !out.contains(&output) {
+                out.push(output);
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::depfile::DepStyle;
+use crate::diagnostics::DiagnosticFormat;
+use crate::install::InstallRule;
+use crate::runner::{CONSOLE_POOL, MessageFormat, Runner, Task, TaskSpec};
+use crate::state::StateDb;
+use mlua::{FromLuaMulti, IntoLuaMulti, Lua, MultiValue, Table};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/*
+TASK GRAPH
+Tasks generated by the lang-script hooks, before anything is executed.
+Every hook runs with `current_target` set, so the tasks it creates through
+`liebe.task` are attributed to that target and run from its project
+directory. Once all hooks ran, `link` adds the edges implied by dependencies
+between targets. Targets are identified by their workspace label.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct TaskGraph {
+    pub tasks: Vec<TaskSpec>,
+    // Depth of the pools declared by the lang-scripts
+    pub pools: BTreeMap<String, usize>,
+    // What `liebe install` copies, by target
+    pub installs: Vec<InstallRule>,
+    // Labels of the targets each target depends on
+    target_deps: BTreeMap<String, Vec<String>>,
+    current_target: String,
+    current_dir: PathBuf,
+}
+
+impl TaskGraph {
+    pub fn begin_target(&mut self, label: &str, deps: Vec<String>, dir: &Path) {
+        self.current_target = label.to_string();
+        self.current_dir = dir.to_path_buf();
+        self.target_deps.insert(label.to_string(), deps);
+    }
+
+    // Inputs and outputs are stored as absolute paths so they can be compared across projects
+    pub fn add(&mut self, mut spec: TaskSpec) -> usize {
+        spec.target = self.current_target.clone();
+        let cwd = match &spec.cwd {
+            Some(cwd) => self.current_dir.join(cwd),
+            None => self.current_dir.clone(),
+        };
+        let absolute = |paths: Vec<String>| {
+            paths
+                .into_iter()
+                .map(|p| cwd.join(p).display().to_string())
+                .collect()
+        };
+        spec.inputs = absolute(spec.inputs);
+        spec.outputs = absolute(spec.outputs);
+        if let Some(DepStyle::Make(path)) = &spec.depfile {
+            spec.depfile = Some(DepStyle::Make(cwd.join(path).display().to_string()));
+        }
+        spec.cwd = Some(cwd.display().to_string());
+        self.tasks.push(spec);
+        self.tasks.len() - 1
+    }
+
+    // Label of a target referenced from a hook of the current target
+    fn qualify(&self, reference: &str) -> String {
+        let prefix = match self.current_target.rsplit_once(':') {
+            Some((member, _)) if self.current_target.starts_with("//") => member,
+            _ => "",
+        };
+        match reference.strip_prefix("//") {
+            // The root project's targets are labelled by their bare name
+            Some(rest) if rest.starts_with(':') => rest[1..].to_string(),
+            Some(_) => reference.to_string(),
+            None if prefix.is_empty() => reference.trim_start_matches(':').to_string(),
+            None => format!("{prefix}:{}", reference.trim_start_matches(':')),
+        }
+    }
+
+    // Files are taken from the project directory like the inputs of tasks
+    pub fn add_install(&mut self, files: Vec<String>, dir: &str, mode: u32) {
+        let files = files
+            .into_iter()
+            .map(|f| self.current_dir.join(f).display().to_string())
+            .collect();
+        self.installs.push(InstallRule {
+            target: self.current_target.clone(),
+            files,
+            dir: dir.to_string(),
+            mode,
+        });
+    }
+
+    // Drops what a target asked to install, for targets declared with `install = false`
+    pub fn skip_install(&mut self, target: &str) {
+        self.installs.retain(|rule| rule.target != target);
+    }
+
+    pub fn target_tasks<'a>(&'a self, target: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter(move |(_, t)| t.target == target)
+            .map(|(id, _)| id)
+    }
+
+    pub fn outputs_of(&self, target: &str) -> Vec<String> {
+        self.target_tasks(target)
+            .flat_map(|id| self.tasks[id].outputs.clone())
+            .collect()
+    }
+
+    // Outputs of a target followed by those of everything it depends on, each target once
+    pub fn transitive_outputs_of(&self, target: &str) -> Vec<String> {
+        let mut seen = vec![target.to_string()];
+        let mut outputs = Vec::new();
+        let mut i = 0;
+        while i < seen.len() {
+            outputs.extend(self.outputs_of(&seen[i]));
+            for dep in self.target_deps.get(&seen[i]).into_iter().flatten() {
+                if !seen.contains(dep) {
+                    seen.push(dep.clone());
+                }
+            }
+            i += 1;
+        }
+        outputs
+    }
+
+    /*
+    Every task of a target waits for all the tasks of the targets it depends on.
+    These edges only order the tasks, a task is rebuilt because of another
+    target through the inputs it declares.
+    */
+    pub fn link(&mut self) {
+        for id in 0..self.tasks.len() {
+            let mut deps: Vec<usize> = self
+                .target_deps
+                .get(&self.tasks[id].target)
+                .into_iter()
+                .flatten()
+                .flat_map(|dep| self.target_tasks(dep).collect::<Vec<_>>())
+                .collect();
+            let task = &mut self.tasks[id];
+            deps.retain(|d| !task.deps.contains(d) && !task.order_deps.contains(d));
+            task.order_deps.extend(deps);
+        }
+    }
+
+    // Targets in the order their tasks were generated, with those tasks
+    pub fn targets(&self) -> Vec<(&str, Vec<usize>)> {
+        let mut targets: Vec<(&str, Vec<usize>)> = Vec::new();
+        for (id, spec) in self.tasks.iter().enumerate() {
+            match targets.iter_mut().find(|(label, _)| *label == spec.target) {
+                Some((_, ids)) => ids.push(id),
+                None => targets.push((&spec.target, vec![id])),
+            }
+        }
+        targets
+    }
+
+    // Inputs no task produces, with those the tasks discovered when they last ran
+    pub fn source_files(&self, state: &StateDb) -> Vec<String> {
+        let outputs: BTreeSet<&String> = self.tasks.iter().flat_map(|t| &t.outputs).collect();
+        let mut sources: BTreeSet<&String> = BTreeSet::new();
+        for spec in &self.tasks {
+            let discovered = state.record(spec).map(|r| &r.discovered[..]).unwrap_or(&[]);
+            sources.extend(spec.inputs.iter().chain(discovered));
+        }
+        sources
+            .into_iter()
+            .filter(|s| !outputs.contains(s))
+            .cloned()
+            .collect()
+    }
+
+    // Tasks with their id and the pools, as printed by `liebe tasks --json`
+    pub fn to_json(&self) -> Value {
+        let tasks: Vec<Value> = self
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(id, spec)| {
+                let mut task = spec.to_json();
+                task["id"] = id.into();
+                task
+            })
+            .collect();
+        json!({ "tasks": tasks, "pools": self.pools })
+    }
+
+    pub fn from_json(value: &Value) -> Self {
+        Self {
+            tasks: value["tasks"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(TaskSpec::from_json)
+                .collect(),
+            pools: value["pools"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_u64()? as usize)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn into_runner(self) -> Runner {
+        let mut runner = Runner::new().with_pools(self.pools);
+        for spec in self.tasks {
+            runner.add_task(Task::from_spec(spec));
+        }
+        runner
+    }
+}
+
+fn graph_error() -> mlua::Error {
+    mlua::Error::runtime("task graph is not available")
+}
+
+/*
+liebe.task { cmd = { ... }, name = ?, inputs = ?, outputs = ?, deps = ?, cwd = ?,
+             env = ?, depfile = ?, showincludes = ?, pool = ?, messages = ?,
+             diagnostics = ? }
+Returns an id that can be listed in the `deps` of later tasks. A relative
+`cwd` is taken from the project directory, which is also the default.
+Headers the task includes are tracked from a Makefile style `depfile` it
+writes, or from the /showIncludes notes it prints with `showincludes = true`.
+`messages = "cargo"` reads the JSON messages of cargo on stdout instead of
+printing them, see cargo.rs. `diagnostics = "gcc" | "rustc"` counts the
+errors and warnings of the compiler, see diagnostics.rs.
+*/
+pub fn lua_task(lua: &Lua, args: MultiValue) -> Result<MultiValue, mlua::Error> {
+    let table = Table::from_lua_multi(args, lua)?;
+    let cmd: Vec<String> = table.get("cmd")?;
+    if cmd.is_empty() {
+        return Err(mlua::Error::runtime("liebe.task: `cmd` must not be empty"));
+    }
+    let outputs: Vec<String> = table.get::<Option<_>>("outputs")?.unwrap_or_default();
+    let name = table
+        .get::<Option<String>>("name")?
+        .or_else(|| outputs.first().cloned())
+        .unwrap_or_else(|| cmd.join(" "));
+
+    let mut graph = lua.app_data_mut::<TaskGraph>().ok_or_else(graph_error)?;
+    let deps: Vec<usize> = table.get::<Option<_>>("deps")?.unwrap_or_default();
+    if let Some(dep) = deps.iter().find(|&&d| d >= graph.tasks.len()) {
+        return Err(mlua::Error::runtime(format!(
+            "liebe.task: `{name}` depends on unknown task id {dep}"
+        )));
+    }
+    let pool: Option<String> = table.get("pool")?;
+    let undeclared = pool
+        .as_ref()
+        .filter(|p| *p != CONSOLE_POOL && !graph.pools.contains_key(*p));
+    if let Some(pool) = undeclared {
+        return Err(mlua::Error::runtime(format!(
+            "liebe.task: `{name}` uses undeclared pool `{pool}`"
+        )));
+    }
+    let depfile = match table.get::<Option<String>>("depfile")? {
+        Some(path) => Some(DepStyle::Make(path)),
+        None if table.get::<Option<bool>>("showincludes")? == Some(true) => Some(DepStyle::Msvc),
+        None => None,
+    };
+    let messages = match table.get::<Option<String>>("messages")?.as_deref() {
+        None => None,
+        Some("cargo") => Some(MessageFormat::Cargo),
+        Some(other) => {
+            return Err(mlua::Error::runtime(format!(
+                "liebe.task: `{name}` has unknown messages format `{other}`"
+            )));
+        }
+    };
+    let format: Option<String> = table.get("diagnostics")?;
+    let diagnostics = format.as_deref().map(DiagnosticFormat::parse);
+    if let (Some(format), Some(None)) = (&format, diagnostics) {
+        return Err(mlua::Error::runtime(format!(
+            "liebe.task: `{name}` has unknown diagnostics format `{format}`"
+        )));
+    }
+    let diagnostics = diagnostics.flatten();
+    let id = graph.add(TaskSpec {
+        name,
+        cmd,
+        cwd: table.get("cwd")?,
+        inputs: table.get::<Option<_>>("inputs")?.unwrap_or_default(),
+        outputs,
+        deps,
+        depfile,
+        env: table.get::<Option<_>>("env")?.unwrap_or_default(),
+        pool,
+        messages,
+        diagnostics,
+        ..Default::default()
+    });
+    id.into_lua_multi(lua)
+}
+
+/*
+liebe.outputs(target, transitive?): outputs of the tasks already generated for
+a target. With `transitive` the outputs of its dependencies follow, in the
+order a linker wants static libraries.
+*/
+pub fn lua_outputs(lua: &Lua, args: MultiValue) -> Result<MultiValue, mlua::Error> {
+    let (target, transitive) = <(String, Option<bool>)>::from_lua_multi(args, lua)?;
+    let graph = lua.app_data_ref::<TaskGraph>().ok_or_else(graph_error)?;
+    let target = graph.qualify(&target);
+    if transitive.unwrap_or(false) {
+        graph.transitive_outputs_of(&target).into_lua_multi(lua)
+    } else {
+        graph.outputs_of(&target).into_lua_multi(lua)
+    }
+}
+
+// liebe.pool(name, depth): at most `depth` tasks using the pool run at once
+pub fn lua_pool(lua: &Lua, args: MultiValue) -> Result<MultiValue, mlua::Error> {
+    let (name, depth) = <(String, usize)>::from_lua_multi(args, lua)?;
+    if name == CONSOLE_POOL || depth == 0 {
+        return Err(mlua::Error::runtime(format!(
+            "liebe.pool: cannot declare `{name}` with depth {depth}"
+        )));
+    }
+    let mut graph = lua.app_data_mut::<TaskGraph>().ok_or_else(graph_error)?;
+    graph.pools.insert(name, depth);
+    Ok(MultiValue::new())
+}
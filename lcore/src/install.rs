@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::empty_err;
+use crate::error::{ExitOnError, LiebeError};
+use crate::graph::TaskGraph;
+use mlua::{FromLuaMulti, Lua, MultiValue, Table};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "install.toml";
+
+// Mode of installed files unless a rule says otherwise
+pub const DEFAULT_MODE: u32 = 0o644;
+
+// Files of a target copied to `dir` under the prefix by `liebe install`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstallRule {
+    pub target: String,
+    // Absolute paths, often outputs of the target's tasks
+    pub files: Vec<String>,
+    // Relative to the prefix, eg. `bin` or `include/foo`
+    pub dir: String,
+    pub mode: u32,
+}
+
+// Octal permissions as written in liebe.toml or lua, eg. "755"
+pub fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|m| *m <= 0o7777)
+        .ok_or_else(|| format!("`{mode}` is not an octal file mode"))
+}
+
+// A `dir` has to stay under the prefix, and so under DESTDIR
+pub fn check_dir(dir: &str) -> Result<(), String> {
+    let path = Path::new(dir);
+    if path.is_absolute() {
+        return Err(format!(
+            "install dir `{dir}` must be relative to the prefix"
+        ));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("install dir `{dir}` must not contain `..`"));
+    }
+    Ok(())
+}
+
+/*
+liebe.install { files = { ... }, dir = "bin", mode = ? }
+Installs the files to `dir` under the prefix, with `mode` ("644" by default).
+Lang-scripts call it from their build hook for what they build, relative
+paths are taken from the project directory.
+*/
+pub fn lua_install(lua: &Lua, args: MultiValue) -> Result<MultiValue, mlua::Error> {
+    let table = Table::from_lua_multi(args, lua)?;
+    let files: Vec<String> = table.get("files")?;
+    let dir: String = table.get("dir")?;
+    check_dir(&dir).map_err(|e| mlua::Error::runtime(format!("liebe.install: {e}")))?;
+    let mode = match table.get::<Option<String>>("mode")? {
+        Some(mode) => {
+            parse_mode(&mode).map_err(|e| mlua::Error::runtime(format!("liebe.install: {e}")))?
+        }
+        None => DEFAULT_MODE,
+    };
+    let mut graph = lua
+        .app_data_mut::<TaskGraph>()
+        .ok_or_else(|| mlua::Error::runtime("task graph is not available"))?;
+    graph.add_install(files, &dir, mode);
+    Ok(MultiValue::new())
+}
+
+/*
+INSTALL MANIFEST
+Kept in the cache dir, it lists every file `liebe install` wrote and the
+directories it had to create, so that `liebe uninstall` removes exactly
+those. Installing again adds to it.
+*/
+#[derive(Debug, Default)]
+pub struct Manifest {
+    path: PathBuf,
+    pub files: Vec<String>,
+    pub dirs: Vec<String>,
+}
+
+impl Manifest {
+    pub fn load(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(MANIFEST_FILE);
+        let table: toml::Table = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default();
+        let strings = |key: &str| -> Vec<String> {
+            table
+                .get(key)
+                .and_then(toml::Value::as_array)
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            files: strings("files"),
+            dirs: strings("dirs"),
+            path,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.dirs.is_empty()
+    }
+
+    fn add(list: &mut Vec<String>, path: &Path) {
+        let path = path.display().to_string();
+        if !list.contains(&path) {
+            list.push(path);
+        }
+    }
+
+    // An empty manifest is removed rather than written
+    pub fn save(&self) {
+        let path = self.path.display().to_string();
+        if self.is_empty() {
+            let _ = fs::remove_file(&self.path);
+            return;
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).log(LiebeError::CannotWriteFile(path.clone()));
+        }
+        let mut table = toml::Table::new();
+        table.insert("files".into(), self.files.clone().into());
+        table.insert("dirs".into(), self.dirs.clone().into());
+        let contents = toml::to_string(&table).log(empty_err!(InvalidConf));
+        fs::write(&self.path, contents).log(LiebeError::CannotWriteFile(path));
+    }
+}
+
+// Creates `dir` and records the directories that did not exist yet
+fn create_dir(dir: &Path, manifest: &mut Manifest) {
+    let missing: Vec<&Path> = dir.ancestors().take_while(|d| !d.exists()).collect();
+    fs::create_dir_all(dir).log(LiebeError::CannotWriteFile(dir.display().to_string()));
+    for created in missing {
+        Manifest::add(&mut manifest.dirs, created);
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .log(LiebeError::CannotWriteFile(path.display().to_string()));
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &Path, _: u32) {}
+
+/*
+Copies the files of the rules to `root`, the prefix below the DESTDIR when
+there is one. An installed file is replaced rather than written over, so a
+running program can be installed again.
+*/
+pub fn install(rules: &[InstallRule], root: &Path, manifest: &mut Manifest) -> Vec<PathBuf> {
+    let mut installed = Vec::new();
+    for rule in rules {
+        let dir = root.join(&rule.dir);
+        create_dir(&dir, manifest);
+        for file in &rule.files {
+            let source = Path::new(file);
+            let Some(name) = source.file_name() else {
+                continue;
+            };
+            let dest = dir.join(name);
+            if dest.is_file() {
+                fs::remove_file(&dest).log(LiebeError::CannotWriteFile(dest.display().to_string()));
+            }
+            fs::copy(source, &dest).log(LiebeError::CannotReadFile(file));
+            set_mode(&dest, rule.mode);
+            Manifest::add(&mut manifest.files, &dest);
+            installed.push(dest);
+        }
+    }
+    manifest.save();
+    installed
+}
+
+// Removes what the manifest lists, directories only if nothing else was put in them since
+pub fn uninstall(manifest: &mut Manifest) -> Vec<String> {
+    let mut removed = Vec::new();
+    for file in &manifest.files {
+        if fs::remove_file(file).is_ok() {
+            removed.push(file.clone());
+        }
+    }
+    // Deepest directories first
+    let mut dirs = manifest.dirs.clone();
+    dirs.sort_by_key(|d| std::cmp::Reverse(Path::new(d).components().count()));
+    for dir in dirs {
+        if fs::remove_dir(&dir).is_ok() {
+            removed.push(dir);
+        }
+    }
+    manifest.files.clear();
+    manifest.dirs.clear();
+    manifest.save();
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirs_stay_under_the_prefix() {
+        assert!(check_dir("bin").is_ok());
+        assert!(check_dir("share/doc/app").is_ok());
+        assert!(check_dir("").is_ok());
+        assert!(check_dir("/tmp/escaped").is_err());
+        assert!(check_dir("../etc").is_err());
+        assert!(check_dir("share/../../etc").is_err());
+    }
+}
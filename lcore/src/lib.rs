@@ -1,8 +1,36 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 coppamocha
+pub mod build;
+pub mod cargo;
+pub mod clean;
+pub mod compdb;
+#[cfg(target_os = "linux")]
+pub mod daemon;
+pub mod depfile;
+pub mod diagnostics;
+pub mod dot;
 pub mod error;
+pub mod exporter;
+pub mod graph;
+pub mod install;
+pub mod logs;
 pub mod luaapi;
 pub mod luaexport;
+pub mod make;
+pub mod modules;
+pub mod ninja;
+pub mod options;
+pub mod profile;
+pub mod report;
 pub mod runner;
+pub mod scaffold;
+pub mod schema;
+pub mod scripts;
 pub mod slidingvec;
+pub mod state;
+pub mod target;
+pub mod testing;
 pub mod utils;
+#[cfg(target_os = "linux")]
+pub mod watch;
+pub mod workspace;
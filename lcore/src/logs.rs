@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::error::{ExitOnError, LiebeError, fail};
+use crate::report;
+use crate::runner::{Event, Task, TaskStatus};
+use crate::utils::did_you_mean;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const LOGS_DIR: &str = "logs";
+
+const INDEX_FILE: &str = "index.toml";
+
+// Leaves room for the suffix telling apart tasks of the same name
+const MAX_STEM: usize = 100;
+
+// Runs whose logs are kept, the older ones are removed when a new run starts
+pub const KEPT_RUNS: usize = 10;
+
+/*
+TASK LOGS
+Every task that ran writes its command, its stdout and stderr and how it
+exited to `.liebe/logs/<run-id>/<task>.log`, so the output of a failed task
+can be read again without the output of the tasks running next to it. Run
+ids sort in the order the runs started. A run only gets a directory once one
+of its tasks finished, a build with nothing to do keeps the previous logs.
+The index of a run lists its tasks in the order they finished.
+*/
+
+// A task of a run as recorded in its index
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub name: String,
+    pub target: String,
+    // Name of the log file in the directory of the run
+    pub file: String,
+    pub success: bool,
+    pub exit_code: Option<i64>,
+}
+
+// Keeps letters, digits, dots and dashes of a task name, eg. `cc src/main.c` -> `cc_src_main.c`
+fn file_stem(name: &str) -> String {
+    // Names default to the whole command line, file names are limited to 255 bytes
+    let stem: String = name
+        .chars()
+        .take(MAX_STEM)
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    match stem.trim_matches('.') {
+        // Names of dots only would give hidden files
+        "" => "task".into(),
+        stem => stem.to_string(),
+    }
+}
+
+fn run_dirs(cache_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(cache_dir.join(LOGS_DIR))
+        .into_iter()
+        .flatten()
+        .map_while(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+#[derive(Debug)]
+pub struct RunLog {
+    cache_dir: PathBuf,
+    id: String,
+    entries: Vec<LogEntry>,
+}
+
+impl RunLog {
+    pub fn new(cache_dir: &Path) -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        Self {
+            cache_dir: cache_dir.to_path_buf(),
+            id: format!("{millis:013}-{}", process::id()),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.cache_dir.join(LOGS_DIR).join(&self.id)
+    }
+
+    // Drops the oldest runs so that KEPT_RUNS remain with the one starting
+    fn rotate(&self) {
+        let dirs = run_dirs(&self.cache_dir);
+        let old = dirs.len().saturating_sub(KEPT_RUNS - 1);
+        for dir in &dirs[..old] {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    // Writes the log of a task that finished, the other events are of no interest
+    pub fn record(&mut self, event: &Event) {
+        let Event::Finished { task, .. } = *event else {
+            return;
+        };
+        // The build goes on without the log
+        if let Err(e) = self.write(task) {
+            eprintln!("Cannot write the log of `{}`: {e}", task.spec().name);
+        }
+    }
+
+    fn write(&mut self, task: &Task) -> io::Result<()> {
+        let dir = self.dir();
+        if self.entries.is_empty() {
+            self.rotate();
+            fs::create_dir_all(&dir)?;
+        }
+        // Tasks of different targets may have the same name
+        let stem = file_stem(&task.spec().name);
+        let mut file = format!("{stem}.log");
+        let mut n = 1;
+        while self.entries.iter().any(|e| e.file == file) {
+            n += 1;
+            file = format!("{stem}-{n}.log");
+        }
+        fs::write(dir.join(&file), Self::contents(task))?;
+        self.entries.push(LogEntry {
+            name: task.spec().name.clone(),
+            target: task.spec().target.clone(),
+            file,
+            success: task.status() == TaskStatus::completed(),
+            exit_code: task.exit_code().map(i64::from),
+        });
+        self.save()
+    }
+
+    fn contents(task: &Task) -> String {
+        let mut lines = vec![format!("$ {}", task.cmd().join(" "))];
+        lines.extend_from_slice(task.output());
+        lines.push(report::exit_message(task));
+        lines.join("\n") + "\n"
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let tasks: Vec<toml::Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut table = toml::Table::new();
+                table.insert("name".into(), entry.name.clone().into());
+                table.insert("target".into(), entry.target.clone().into());
+                table.insert("file".into(), entry.file.clone().into());
+                table.insert("success".into(), entry.success.into());
+                if let Some(code) = entry.exit_code {
+                    table.insert("exit_code".into(), code.into());
+                }
+                table.into()
+            })
+            .collect();
+        let mut index = toml::Table::new();
+        index.insert("tasks".into(), tasks.into());
+        let contents = toml::to_string(&index).map_err(io::Error::other)?;
+        fs::write(self.dir().join(INDEX_FILE), contents)
+    }
+}
+
+// The logs of the last run that had tasks to run
+#[derive(Debug, Default)]
+pub struct LastRun {
+    pub dir: PathBuf,
+    pub entries: Vec<LogEntry>,
+}
+
+impl LastRun {
+    pub fn load(cache_dir: &Path) -> Option<Self> {
+        let dir = run_dirs(cache_dir).pop()?;
+        let index: toml::Table = fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default();
+        let entries = index
+            .get("tasks")
+            .and_then(toml::Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|task| {
+                let string = |key: &str| task.get(key)?.as_str().map(String::from);
+                Some(LogEntry {
+                    name: string("name")?,
+                    target: string("target").unwrap_or_default(),
+                    file: string("file")?,
+                    success: task.get("success")?.as_bool()?,
+                    exit_code: task.get("exit_code").and_then(toml::Value::as_integer),
+                })
+            })
+            .collect();
+        Some(Self { dir, entries })
+    }
+
+    pub fn failed(&self) -> Vec<&LogEntry> {
+        self.entries.iter().filter(|e| !e.success).collect()
+    }
+
+    // Tasks named `name`, or else whose name contains it
+    pub fn find(&self, name: &str) -> Vec<&LogEntry> {
+        let exact: Vec<&LogEntry> = self.entries.iter().filter(|e| e.name == name).collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+        let found: Vec<&LogEntry> = self
+            .entries
+            .iter()
+            .filter(|e| e.name.contains(name))
+            .collect();
+        if found.is_empty() {
+            let hint = did_you_mean(name, self.entries.iter().map(|e| e.name.as_str()))
+                .map(|s| format!(", did you mean `{s}`?"))
+                .unwrap_or_default();
+            fail(LiebeError::UnknownTask(format!(
+                "`{name}` did not run in the last build{hint}"
+            )));
+        }
+        found
+    }
+
+    pub fn read(&self, entry: &LogEntry) -> String {
+        let path = self.dir.join(&entry.file);
+        fs::read_to_string(&path).log(LiebeError::CannotReadFile(path.display().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_stems_are_safe_file_names() {
+        assert_eq!(file_stem("cc src/main.c"), "cc_src_main.c");
+        assert_eq!(file_stem("../up"), "_up");
+        assert_eq!(file_stem("."), "task");
+        assert_eq!(file_stem("..."), "task");
+        assert_eq!(file_stem(""), "task");
+        assert_eq!(file_stem(&"x".repeat(1000)).len(), MAX_STEM);
+    }
+}
@@ -1,29 +1,58 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 coppamocha
+use crate::cargo;
 use crate::empty_err;
 use crate::error::*;
+use crate::graph::{self, TaskGraph};
+use crate::install;
+use crate::luaexport::{LuaExtension, toml_to_lua};
+use crate::modules::{self, ModuleLoader};
+use crate::report;
+use crate::schema::{self, SchemaExtensions};
+use crate::scripts::{self, BUNDLED_PREFIX};
 use crate::utils::{self, *};
+use mlua::AppDataRefMut;
 use mlua::prelude::*;
+use std::env;
 use std::fmt::Debug;
 use std::fs;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 use toml;
 
+// $(PROJECT) is the directory holding the liebe.toml, $(USER) is ~/.config/liebe
 const SEARCH_DIRS: &[&str] = &[
     "$(PROG)/scripts/",
     "$(PROG)/extensions/",
-    "$(PWD)/",
-    "$(PWD)/liebe/",
+    "$(USER)/",
+    "$(PROJECT)/",
+    "$(PROJECT)/liebe/",
 ];
 
+fn user_dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_default()
+        .join("liebe")
+}
+
 pub struct LuaApi {
     config: toml::Value,
+    dir: PathBuf,
+    // Lang-script found in the search dirs, `None` for a bundled one
+    script: Option<PathBuf>,
     lua: Lua,
 }
 
 impl LuaApi {
-    pub fn new(config_path: &str) -> Self {
-        let config_path = config_path.resolve();
+    // Relative paths in the config are taken from the directory holding it, not from the cwd
+    pub fn new(config_path: &Path) -> Self {
+        let dir = config_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let config_path = config_path.display().to_string();
         let mut file =
             fs::File::open(&config_path).log(LiebeError::CannotOpenFile(config_path.clone()));
         let mut contents = String::new();
@@ -32,11 +61,77 @@ impl LuaApi {
 
         let config = toml::from_str(&contents).log(empty_err!(InvalidConf));
 
-        let lua = Lua::new();
+        let mut lua = Lua::new();
         lua.load_std_libs(LuaStdLib::ALL_SAFE)
             .log(empty_err!(CantOpenStdLibs));
+        lua.set_app_data(SchemaExtensions::default());
+        lua.register_fn(&schema::lua_extend_schema, "schema", "liebe")
+            .log(LiebeError::CannotInjectContext("liebe.schema"));
+        lua.set_app_data(TaskGraph::default());
+        lua.register_fn(&graph::lua_task, "task", "liebe")
+            .log(LiebeError::CannotInjectContext("liebe.task"));
+        lua.register_fn(&graph::lua_outputs, "outputs", "liebe")
+            .log(LiebeError::CannotInjectContext("liebe.outputs"));
+        lua.register_fn(&graph::lua_pool, "pool", "liebe")
+            .log(LiebeError::CannotInjectContext("liebe.pool"));
+        lua.register_fn(&install::lua_install, "install", "liebe")
+            .log(LiebeError::CannotInjectContext("liebe.install"));
+        lua.register_fn(&cargo::lua_metadata, "cargo_metadata", "liebe")
+            .log(LiebeError::CannotInjectContext("liebe.cargo_metadata"));
+
+        let liebe: mlua::Table = lua
+            .globals()
+            .get("liebe")
+            .log(empty_err!(CannotCreateTable));
+        liebe
+            .set(
+                "config",
+                toml_to_lua(&lua, &config).log(LiebeError::CannotInjectContext("liebe.config")),
+            )
+            .log(LiebeError::CannotInjectContext("liebe.config"));
 
-        Self { config, lua }
+        let api = Self {
+            config,
+            dir,
+            script: None,
+            lua,
+        };
+        let search_dirs = api
+            .search_dirs()
+            .iter()
+            .map(|d| d.resolve().into())
+            .collect();
+        api.lua.set_app_data(ModuleLoader::new(search_dirs));
+        let require = api
+            .lua
+            .create_function(modules::lua_require)
+            .log(LiebeError::CannotInjectContext("require"));
+        api.lua
+            .globals()
+            .set("require", require)
+            .log(LiebeError::CannotInjectContext("require"));
+        if report::get_json() {
+            let print = api
+                .lua
+                .create_function(report::lua_print)
+                .log(LiebeError::CannotInjectContext("print"));
+            api.lua
+                .globals()
+                .set("print", print)
+                .log(LiebeError::CannotInjectContext("print"));
+        }
+        api
+    }
+
+    // Where the lang-script and the modules it requires are looked up, in order
+    fn search_dirs(&self) -> Vec<String> {
+        SEARCH_DIRS
+            .iter()
+            .map(|d| {
+                d.replace("$(PROJECT)", &self.dir.display().to_string())
+                    .replace("$(USER)", &user_dir().display().to_string())
+            })
+            .collect()
     }
 
     pub fn invoke(&mut self) {
@@ -46,26 +141,96 @@ impl LuaApi {
             .log(LiebeError::CantFindFieldInConf("lang-script"))
             .as_str()
             .log(empty_err!(InvalidConf));
-        let mut contents = String::new();
-        let mut file = fs::File::open(utils::search_file_in_dirs(SEARCH_DIRS, lang_script).log(
-            LiebeError::CannotOpenFile(format!(
-                "searched in {} for {}",
-                SEARCH_DIRS.join(", ").resolve(),
-                lang_script
-            )),
-        ))
-        .log(LiebeError::CannotOpenFile(format!(
-            "{} in {:?}",
-            lang_script, SEARCH_DIRS
-        )));
+        let search_dirs = self.search_dirs();
+        let search_dirs = search_dirs.iter().map(String::as_str).collect::<Vec<_>>();
+        // The bundled scripts are the last resort
+        let (path, contents) = match utils::search_file_in_dirs(&search_dirs, lang_script) {
+            Some(path) => {
+                let mut contents = String::new();
+                let mut file = fs::File::open(&path).log(LiebeError::CannotOpenFile(format!(
+                    "{} in {:?}",
+                    lang_script, search_dirs
+                )));
+                file.read_to_string(&mut contents)
+                    .log(LiebeError::CannotReadFile(lang_script.to_string()));
+                (path, contents)
+            }
+            None => {
+                let source =
+                    scripts::bundled(lang_script).log(LiebeError::CannotOpenFile(format!(
+                        "searched in {} for {}",
+                        search_dirs.join(", ").resolve(),
+                        lang_script
+                    )));
+                (format!("{BUNDLED_PREFIX}{lang_script}"), source.to_string())
+            }
+        };
 
-        file.read_to_string(&mut contents)
-            .log(LiebeError::CannotReadFile(lang_script.to_string()));
+        if !path.starts_with(BUNDLED_PREFIX) {
+            self.script = Some(PathBuf::from(&path));
+        }
+        if let Err(e) = self.lua.load(contents).set_name(format!("@{path}")).exec() {
+            fail(LiebeError::LangScriptError(modules::describe_error(&e)));
+        }
+
+        self.validate();
+    }
+
+    // Checks the configuration against the core schema and the sections registered by the lang-script
+    fn validate(&self) {
+        let extensions = self
+            .lua
+            .app_data_ref::<SchemaExtensions>()
+            .log(empty_err!(InvalidConf));
+        let errors = schema::validate(&self.config, &extensions);
+        if !errors.is_empty() {
+            let report = errors
+                .iter()
+                .map(|e| format!("  {e}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            fail(LiebeError::InvalidSchema(report));
+        }
+    }
+
+    pub fn config(&self) -> &toml::Value {
+        &self.config
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
 
+    // Lua files read from disk so far, the lang-script and the modules it required
+    pub fn scripts(&self) -> Vec<PathBuf> {
+        let mut scripts: Vec<PathBuf> = self.script.iter().cloned().collect();
+        if let Some(loader) = self.lua.app_data_ref::<ModuleLoader>() {
+            scripts.extend_from_slice(loader.files());
+        }
+        scripts
+    }
+
+    pub fn graph(&self) -> AppDataRefMut<'_, TaskGraph> {
         self.lua
-            .load(contents)
-            .exec()
-            .expect("Syntax error in lang-script");
+            .app_data_mut::<TaskGraph>()
+            .log(empty_err!(InvalidConf))
+    }
+
+    // Hands over the tasks generated so far and starts a new graph, keeping the declared pools
+    pub fn take_graph(&self) -> TaskGraph {
+        let mut current = self.graph();
+        let pools = current.pools.clone();
+        let graph = std::mem::take(&mut *current);
+        current.pools = pools;
+        graph
+    }
+
+    // Workspace members share one graph, which moves between their lua states
+    pub fn set_graph(&self, mut graph: TaskGraph) {
+        let mut current = self.graph();
+        // Pools are declared when the lang-script is loaded, before any graph arrives
+        graph.pools.append(&mut current.pools);
+        *current = graph;
     }
 
     pub fn call<G>(&self, func: &str) -> G
@@ -38,3 +38,53 @@ impl LuaExtension for Lua {
         Ok(())
     }
 }
+
+pub fn toml_to_lua(lua: &Lua, value: &toml::Value) -> Result<Value, mlua::Error> {
+    Ok(match value {
+        toml::Value::String(s) => Value::String(lua.create_string(s)?),
+        toml::Value::Integer(i) => Value::Integer(*i),
+        toml::Value::Float(f) => Value::Number(*f),
+        toml::Value::Boolean(b) => Value::Boolean(*b),
+        toml::Value::Datetime(d) => Value::String(lua.create_string(d.to_string())?),
+        toml::Value::Array(values) => {
+            let t = lua.create_table()?;
+            for v in values {
+                t.push(toml_to_lua(lua, v)?)?;
+            }
+            Value::Table(t)
+        }
+        toml::Value::Table(table) => {
+            let t = lua.create_table()?;
+            for (k, v) in table {
+                t.set(k.as_str(), toml_to_lua(lua, v)?)?;
+            }
+            Value::Table(t)
+        }
+    })
+}
+
+pub fn json_to_lua(lua: &Lua, value: &serde_json::Value) -> Result<Value, mlua::Error> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Boolean(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Number(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::String(lua.create_string(s)?),
+        serde_json::Value::Array(values) => {
+            let t = lua.create_table()?;
+            for v in values {
+                t.push(json_to_lua(lua, v)?)?;
+            }
+            Value::Table(t)
+        }
+        serde_json::Value::Object(object) => {
+            let t = lua.create_table()?;
+            for (k, v) in object {
+                t.set(k.as_str(), json_to_lua(lua, v)?)?;
+            }
+            Value::Table(t)
+        }
+    })
+}
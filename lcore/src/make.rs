@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::depfile::DepStyle;
+use crate::exporter::{self, Regen};
+use crate::graph::TaskGraph;
+use crate::runner::TaskSpec;
+use crate::utils::shell_quote;
+use std::fmt::Write;
+use std::path::Path;
+
+pub const MAKE_FILE: &str = "Makefile";
+
+// Escapes a path in a rule, where whitespace separates paths and colons end the targets
+fn path(p: &str) -> String {
+    p.replace('$', "$$")
+        .replace(' ', "\\ ")
+        .replace(':', "\\:")
+        .replace('#', "\\#")
+}
+
+fn paths(ps: &[String]) -> String {
+    ps.iter().map(|p| path(p)).collect::<Vec<_>>().join(" ")
+}
+
+// Escapes a recipe line, which make only expands before handing it to the shell
+fn recipe(line: &str) -> String {
+    line.replace('$', "$$").replace('\n', " ")
+}
+
+// Directories the outputs go to, made before the task runs
+fn dirs(outputs: &[String]) -> Vec<String> {
+    let mut dirs: Vec<String> = Vec::new();
+    for output in outputs {
+        let Some(dir) = Path::new(output).parent().filter(|d| d.is_absolute()) else {
+            continue;
+        };
+        let dir = dir.display().to_string();
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/*
+A task is a rule for its first output, its other outputs depend on the first
+one with an empty recipe. Make has no grouped targets before 4.3, this way
+the command still runs once.
+*/
+fn rule(out: &mut String, graph: &TaskGraph, id: usize, spec: &TaskSpec) {
+    let outputs = exporter::task_outputs(spec, id);
+    let mut prerequisites = spec.inputs.clone();
+    prerequisites.extend(exporter::outputs_of(graph, &spec.deps, &spec.inputs));
+    let mut order_only = exporter::outputs_of(graph, &spec.order_deps, &prerequisites);
+    order_only.extend(dirs(&spec.outputs));
+
+    if spec.outputs.is_empty() {
+        let _ = writeln!(out, ".PHONY: {}", path(&outputs[0]));
+    }
+    let _ = write!(out, "{}: {}", path(&outputs[0]), paths(&prerequisites));
+    if !order_only.is_empty() {
+        let _ = write!(out, " | {}", paths(&order_only));
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "\t@echo {}", recipe(&shell_quote(&spec.name)));
+    let _ = writeln!(out, "\t@{}", recipe(&exporter::command_line(spec)));
+    for other in &outputs[1..] {
+        let _ = writeln!(out, "{}: {} ;", path(other), path(&outputs[0]));
+    }
+    // Headers found by the compiler on the previous run, made absolute by the recipe
+    if let Some(DepStyle::Make(depfile)) = &spec.depfile {
+        let _ = writeln!(out, "-include {}", path(depfile));
+    }
+}
+
+/*
+Translates the task graph into a GNU Makefile. Every liebe target becomes a
+phony target named by its label, `defaults` are the labels built by a plain
+`make`. Pools have no equivalent, `make -j` limits all the tasks alike.
+Partially written outputs of failed commands are removed, and the Makefile
+regenerates itself when a liebe.toml changes.
+*/
+pub fn generate(graph: &TaskGraph, defaults: &[String], regen: &Regen, file: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Generated by liebe, do not edit");
+    let _ = writeln!(out, "SHELL := /bin/sh");
+    let _ = writeln!(out, ".DELETE_ON_ERROR:");
+    let _ = writeln!(out, ".SUFFIXES:\n");
+    let _ = writeln!(out, ".PHONY: all");
+    let _ = writeln!(out, "all: {}\n", paths(defaults));
+
+    // Make knows the Makefile by the name it was given, usually the bare file name
+    let _ = writeln!(out, "{} {MAKE_FILE}: {}", path(file), paths(&regen.inputs));
+    let _ = writeln!(out, "\t@echo Regenerating {MAKE_FILE}");
+    let _ = writeln!(out, "\t@{}\n", recipe(&regen.command_line()));
+
+    let mut all_dirs: Vec<String> = Vec::new();
+    for (id, spec) in graph.tasks.iter().enumerate() {
+        rule(&mut out, graph, id, spec);
+        let _ = writeln!(out);
+        for dir in dirs(&spec.outputs) {
+            if !all_dirs.contains(&dir) {
+                all_dirs.push(dir);
+            }
+        }
+    }
+    for dir in &all_dirs {
+        let _ = writeln!(
+            out,
+            "{}:\n\t@mkdir -p {}",
+            path(dir),
+            recipe(&shell_quote(dir))
+        );
+    }
+    let _ = writeln!(out);
+
+    for (label, ids) in graph.targets() {
+        let _ = writeln!(out, ".PHONY: {}", path(label));
+        let _ = writeln!(
+            out,
+            "{}: {}",
+            path(label),
+            paths(&exporter::outputs_of(graph, &ids, &[]))
+        );
+    }
+    out
+}
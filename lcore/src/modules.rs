@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::scripts::{self, BUNDLED_PREFIX};
+use mlua::{FromLuaMulti, IntoLuaMulti, Lua, MultiValue, Table, Value};
+use std::fs;
+use std::path::PathBuf;
+
+/*
+MODULES
+`require` is replaced so lang-scripts can share code through the same dirs
+the lang-script itself is searched in. `require "c.flags"` tries
+`<dir>/c/flags.lua` then `<dir>/c/flags/init.lua` in every search dir.
+Modules bundled in the binary are used when no search dir has the module.
+Loaded modules are cached in `package.loaded` like the stock require does.
+*/
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    search_dirs: Vec<PathBuf>,
+    // Modules currently being loaded, innermost last
+    loading: Vec<String>,
+    // Files modules were loaded from
+    files: Vec<PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn new(search_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            search_dirs,
+            loading: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    fn candidates(&self, name: &str) -> Vec<PathBuf> {
+        let path = name.replace('.', "/");
+        self.search_dirs
+            .iter()
+            .flat_map(|dir| {
+                [
+                    dir.join(format!("{path}.lua")),
+                    dir.join(&path).join("init.lua"),
+                ]
+            })
+            .collect()
+    }
+}
+
+// The message of the error that started it all, with the lua stack at that point
+pub fn describe_error(err: &mlua::Error) -> String {
+    let mut err = err;
+    let mut traceback = None;
+    while let mlua::Error::CallbackError {
+        traceback: tb,
+        cause,
+    } = err
+    {
+        traceback = Some(tb);
+        err = cause;
+    }
+    match traceback {
+        Some(tb) => format!("{err}\n{tb}"),
+        None => err.to_string(),
+    }
+}
+
+fn loader_error() -> mlua::Error {
+    mlua::Error::runtime("module loader is not available")
+}
+
+// Removes `name` from the modules being loaded, also when loading it failed
+fn finish_loading(lua: &Lua, name: &str) {
+    if let Some(mut loader) = lua.app_data_mut::<ModuleLoader>() {
+        loader.loading.retain(|m| m != name);
+    }
+}
+
+pub fn lua_require(lua: &Lua, args: MultiValue) -> Result<MultiValue, mlua::Error> {
+    let name = String::from_lua_multi(args, lua)?;
+    let package: Table = lua.globals().get("package")?;
+    let loaded: Table = package.get("loaded")?;
+    let cached: Value = loaded.get(name.as_str())?;
+    if !cached.is_nil() {
+        return cached.into_lua_multi(lua);
+    }
+
+    let candidates = {
+        let mut loader = lua
+            .app_data_mut::<ModuleLoader>()
+            .ok_or_else(loader_error)?;
+        if loader.loading.contains(&name) {
+            let mut chain = loader.loading.clone();
+            chain.push(name);
+            return Err(mlua::Error::runtime(format!(
+                "require: cyclic dependency between modules: {}",
+                chain.join(" -> ")
+            )));
+        }
+        loader.loading.push(name.clone());
+        loader.candidates(&name)
+    };
+
+    // Modules registered from rust through package.preload take precedence
+    let preload: Option<mlua::Function> = package.get::<Table>("preload")?.get(name.as_str())?;
+    let bundled_path = format!("{}.lua", name.replace('.', "/"));
+    let load = |source: &str, chunk: String| {
+        lua.load(source)
+            .set_name(format!("@{chunk}"))
+            .call::<Value>(name.as_str())
+    };
+    let found = candidates.iter().find(|p| p.is_file());
+    if let (None, Some(path), Some(mut loader)) =
+        (&preload, found, lua.app_data_mut::<ModuleLoader>())
+    {
+        loader.files.push(path.clone());
+    }
+    let value = match (preload, found) {
+        (Some(func), _) => func.call::<Value>(name.as_str()),
+        (None, Some(path)) => fs::read_to_string(path)
+            .map_err(mlua::Error::external)
+            .and_then(|source| load(&source, path.display().to_string())),
+        (None, None) => match scripts::bundled(&bundled_path) {
+            Some(source) => load(source, format!("{BUNDLED_PREFIX}{bundled_path}")),
+            None => Err(mlua::Error::runtime(format!(
+                "module `{name}` not found, tried:\n{}",
+                candidates
+                    .iter()
+                    .map(|p| format!("    {}", p.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ))),
+        },
+    };
+    finish_loading(lua, &name);
+
+    // A module returning nothing is still marked as loaded
+    let value = match value? {
+        Value::Nil => Value::Boolean(true),
+        value => value,
+    };
+    loaded.set(name.as_str(), value.clone())?;
+    value.into_lua_multi(lua)
+}
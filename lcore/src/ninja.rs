@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::depfile::DepStyle;
+use crate::exporter::{self, Regen};
+use crate::graph::TaskGraph;
+use crate::runner::TaskSpec;
+use std::fmt::Write;
+use std::path::Path;
+
+pub const NINJA_FILE: &str = "build.ninja";
+
+// Ninja's own state goes with the rest of liebe's cache
+const NINJA_DIR: &str = "ninja";
+
+// Escapes a path in a build line, where spaces and colons separate paths
+fn path(p: &str) -> String {
+    p.replace('$', "$$").replace(' ', "$ ").replace(':', "$:")
+}
+
+fn paths(ps: &[String]) -> String {
+    ps.iter().map(|p| path(p)).collect::<Vec<_>>().join(" ")
+}
+
+// Escapes the value of a variable
+fn value(v: &str) -> String {
+    v.replace('$', "$$").replace('\n', " ")
+}
+
+const RULES: &str = "\
+rule run
+  command = $cmd
+  description = $desc
+  restat = 1
+
+rule run_gcc
+  command = $cmd
+  description = $desc
+  depfile = $depfile
+  deps = gcc
+  restat = 1
+
+rule run_msvc
+  command = $cmd
+  description = $desc
+  deps = msvc
+  restat = 1
+
+rule regen
+  command = $cmd
+  description = Regenerating build.ninja
+  generator = 1
+";
+
+fn edge(out: &mut String, graph: &TaskGraph, id: usize, spec: &TaskSpec) {
+    let outputs = exporter::task_outputs(spec, id);
+    let rule = match &spec.depfile {
+        Some(DepStyle::Make(_)) => "run_gcc",
+        Some(DepStyle::Msvc) => "run_msvc",
+        None => "run",
+    };
+    let implicit = exporter::outputs_of(graph, &spec.deps, &spec.inputs);
+    let order_only = exporter::outputs_of(graph, &spec.order_deps, &spec.inputs);
+
+    let _ = write!(
+        out,
+        "build {}: {rule} {}",
+        paths(&outputs),
+        paths(&spec.inputs)
+    );
+    if !implicit.is_empty() {
+        let _ = write!(out, " | {}", paths(&implicit));
+    }
+    if !order_only.is_empty() {
+        let _ = write!(out, " || {}", paths(&order_only));
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "  cmd = {}", value(&exporter::command_line(spec)));
+    let _ = writeln!(out, "  desc = {}", value(&spec.name));
+    if let Some(DepStyle::Make(depfile)) = &spec.depfile {
+        let _ = writeln!(out, "  depfile = {}", value(depfile));
+    }
+    if let Some(pool) = &spec.pool {
+        let _ = writeln!(out, "  pool = {pool}");
+    }
+}
+
+/*
+Translates the task graph into a build.ninja. Every liebe target becomes a
+phony edge named by its label, `defaults` are the labels built by a plain
+`ninja`. build.ninja regenerates itself when a liebe.toml changes, as long
+as ninja runs in its directory, eg. with `ninja -C <dir>`.
+*/
+pub fn generate(
+    graph: &TaskGraph,
+    defaults: &[String],
+    regen: &Regen,
+    file: &str,
+    cache_dir: &Path,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Generated by liebe, do not edit");
+    let _ = writeln!(out, "ninja_required_version = 1.7");
+    let builddir = cache_dir.join(NINJA_DIR).display().to_string();
+    let _ = writeln!(out, "builddir = {}\n", value(&builddir));
+    let _ = writeln!(out, "{RULES}");
+    for (name, depth) in &graph.pools {
+        let _ = writeln!(out, "pool {name}\n  depth = {depth}\n");
+    }
+
+    // Ninja knows its manifest by the name it loaded, relative to the directory it runs in
+    let manifest = Path::new(file)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| NINJA_FILE.into());
+    let _ = writeln!(
+        out,
+        "build {}: regen {}",
+        path(&manifest),
+        paths(&regen.inputs)
+    );
+    let _ = writeln!(out, "  cmd = {}\n", value(&regen.command_line()));
+
+    for (id, spec) in graph.tasks.iter().enumerate() {
+        edge(&mut out, graph, id, spec);
+        let _ = writeln!(out);
+    }
+
+    for (label, ids) in graph.targets() {
+        let _ = writeln!(
+            out,
+            "build {}: phony {}",
+            path(label),
+            paths(&exporter::outputs_of(graph, &ids, &[]))
+        );
+    }
+    if !defaults.is_empty() {
+        let _ = writeln!(out, "\ndefault {}", paths(defaults));
+    }
+    out
+}
@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::empty_err;
+use crate::error::{ExitOnError, LiebeError, fail};
+use crate::luaexport::toml_to_lua;
+use crate::schema::{Field, Schema};
+use crate::utils::did_you_mean;
+use mlua::{IntoLua, Lua, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const OPTIONS_FILE: &str = "options.toml";
+
+pub fn options_schema() -> Schema {
+    Schema::map(Schema::Table(vec![
+        Field::required(
+            "type",
+            Schema::one_of(&["bool", "string", "integer", "choice", "array"]),
+        ),
+        Field::new("default", Schema::Any),
+        Field::new("help", Schema::String),
+        Field::new("choices", Schema::strings()),
+    ]))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionType {
+    Bool,
+    String,
+    Integer,
+    Choice(Vec<String>),
+    // Comma separated on the command line
+    Array,
+}
+
+impl OptionType {
+    pub fn type_name(&self) -> &str {
+        match self {
+            Self::Bool => "bool",
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Choice(_) => "choice",
+            Self::Array => "array",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOption {
+    pub name: String,
+    pub kind: OptionType,
+    pub default: toml::Value,
+    pub help: Option<String>,
+}
+
+impl BuildOption {
+    fn from_toml(name: &str, value: &toml::Value) -> Self {
+        let choices = value
+            .get("choices")
+            .and_then(toml::Value::as_array)
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect::<Vec<_>>()
+            });
+        let kind = match value.get("type").and_then(toml::Value::as_str) {
+            Some("bool") => OptionType::Bool,
+            Some("integer") => OptionType::Integer,
+            Some("array") => OptionType::Array,
+            Some("choice") => {
+                OptionType::Choice(choices.filter(|c| !c.is_empty()).unwrap_or_else(|| {
+                    fail(LiebeError::InvalidOption(format!(
+                        "`{name}` is a choice but declares no `choices`"
+                    )))
+                }))
+            }
+            _ => OptionType::String,
+        };
+        let default = value
+            .get("default")
+            .cloned()
+            .unwrap_or_else(|| match &kind {
+                OptionType::Bool => toml::Value::Boolean(false),
+                OptionType::Integer => toml::Value::Integer(0),
+                OptionType::Array => toml::Value::Array(Vec::new()),
+                OptionType::Choice(c) => toml::Value::String(c[0].clone()),
+                OptionType::String => toml::Value::String(String::new()),
+            });
+        let option = Self {
+            name: name.into(),
+            kind,
+            default,
+            help: value
+                .get("help")
+                .and_then(toml::Value::as_str)
+                .map(String::from),
+        };
+        if let Err(e) = option.check(&option.default) {
+            fail(LiebeError::InvalidOption(format!(
+                "default of `{name}`: {e}"
+            )));
+        }
+        option
+    }
+
+    fn check(&self, value: &toml::Value) -> Result<(), String> {
+        match (&self.kind, value) {
+            (OptionType::Bool, toml::Value::Boolean(_))
+            | (OptionType::Integer, toml::Value::Integer(_))
+            | (OptionType::String, toml::Value::String(_)) => Ok(()),
+            (OptionType::Array, toml::Value::Array(a)) if a.iter().all(|v| v.is_str()) => Ok(()),
+            (OptionType::Choice(choices), toml::Value::String(s)) => {
+                if choices.contains(s) {
+                    Ok(())
+                } else {
+                    let hint = did_you_mean(s, choices.iter().map(String::as_str))
+                        .map(|c| format!(", did you mean `{c}`?"))
+                        .unwrap_or_default();
+                    Err(format!(
+                        "`{s}` is not one of [{}]{hint}",
+                        choices.join(", ")
+                    ))
+                }
+            }
+            (kind, value) => Err(format!(
+                "expected {}, found {}",
+                kind.type_name(),
+                value.type_str()
+            )),
+        }
+    }
+
+    // Parses a value given on the command line
+    pub fn parse(&self, raw: &str) -> Result<toml::Value, String> {
+        let value = match &self.kind {
+            OptionType::Bool => match raw {
+                "true" | "on" | "yes" | "1" => toml::Value::Boolean(true),
+                "false" | "off" | "no" | "0" => toml::Value::Boolean(false),
+                _ => return Err(format!("`{raw}` is not a boolean")),
+            },
+            OptionType::Integer => toml::Value::Integer(
+                raw.parse()
+                    .map_err(|_| format!("`{raw}` is not an integer"))?,
+            ),
+            OptionType::Array => toml::Value::Array(
+                raw.split(',')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| toml::Value::String(s.trim().into()))
+                    .collect(),
+            ),
+            OptionType::String | OptionType::Choice(_) => toml::Value::String(raw.into()),
+        };
+        self.check(&value)?;
+        Ok(value)
+    }
+}
+
+// Final option values of a build, handed to lua as `build_conf.options`
+#[derive(Debug, Clone, Default)]
+pub struct OptionValues(pub toml::Table);
+
+impl IntoLua for OptionValues {
+    fn into_lua(self, lua: &Lua) -> mlua::Result<Value> {
+        toml_to_lua(lua, &toml::Value::Table(self.0))
+    }
+}
+
+// Options declared in the `[options]` section of liebe.toml
+#[derive(Debug, Default)]
+pub struct Options {
+    declared: BTreeMap<String, BuildOption>,
+}
+
+impl Options {
+    pub fn from_config(config: &toml::Value) -> Self {
+        let declared = config
+            .get("options")
+            .and_then(toml::Value::as_table)
+            .map(|t| {
+                t.iter()
+                    .map(|(name, v)| (name.clone(), BuildOption::from_toml(name, v)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { declared }
+    }
+
+    pub fn declares(&self, name: &str) -> bool {
+        self.declared.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.declared.keys()
+    }
+
+    fn parse_override(&self, define: &str) -> (String, toml::Value) {
+        let (name, raw) = define.split_once('=').unwrap_or_else(|| {
+            fail(LiebeError::InvalidOption(format!(
+                "`-D {define}` must be of the form NAME=VALUE"
+            )))
+        });
+        let Some(option) = self.declared.get(name) else {
+            let hint = did_you_mean(name, self.declared.keys().map(String::as_str))
+                .map(|c| format!(", did you mean `{c}`?"))
+                .unwrap_or_default();
+            fail(LiebeError::InvalidOption(format!(
+                "unknown option `{name}`{hint}"
+            )));
+        };
+        let value = option
+            .parse(raw)
+            .unwrap_or_else(|e| fail(LiebeError::InvalidOption(format!("{name}: {e}"))));
+        (name.to_string(), value)
+    }
+
+    /*
+    Computes the option values of a build directory. Precedence is:
+        -D on the command line > values persisted in the build directory > defaults
+    The result is written back so later invocations keep the overrides, unless
+    `persist` is false.
+    */
+    pub fn resolve(&self, build_dir: &Path, defines: &[String], persist: bool) -> OptionValues {
+        let path = build_dir.join(OPTIONS_FILE);
+        let persisted: toml::Table = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default();
+
+        let mut values = toml::Table::new();
+        for option in self.declared.values() {
+            let value = persisted
+                .get(&option.name)
+                .filter(|v| option.check(v).is_ok())
+                .unwrap_or(&option.default);
+            values.insert(option.name.clone(), value.clone());
+        }
+        for define in defines {
+            let (name, value) = self.parse_override(define);
+            values.insert(name, value);
+        }
+
+        if persist && !values.is_empty() && values != persisted {
+            let path = path.display().to_string();
+            fs::create_dir_all(build_dir).log(LiebeError::CannotWriteFile(path.clone()));
+            let contents = toml::to_string(&values).log(empty_err!(InvalidConf));
+            fs::write(&path, contents).log(LiebeError::CannotWriteFile(path));
+        }
+        OptionValues(values)
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::error::{LiebeError, fail};
+use crate::schema::{Field, Schema};
+use crate::utils::did_you_mean;
+use mlua::{IntoLua, Lua, Value};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub name: String,
+    pub inherits: Option<String>,
+    pub description: Option<String>,
+    pub opt_level: Option<String>,
+    pub debug: Option<bool>,
+    pub defines: Vec<String>,
+    pub flags: Vec<String>,
+}
+
+pub fn profile_schema() -> Schema {
+    Schema::map(Schema::Table(vec![
+        Field::new("inherits", Schema::String),
+        Field::new("description", Schema::String),
+        Field::new(
+            "opt-level",
+            Schema::Either(vec![
+                Schema::Integer,
+                Schema::one_of(&["0", "1", "2", "3", "s", "z", "g"]),
+            ]),
+        ),
+        Field::new("debug", Schema::Bool),
+        Field::new("defines", Schema::strings()),
+        Field::new("flags", Schema::strings()),
+    ]))
+}
+
+impl Profile {
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "debug" => Some(Self {
+                name: name.into(),
+                description: Some("Unoptimized build with debug info".into()),
+                opt_level: Some("0".into()),
+                debug: Some(true),
+                ..Default::default()
+            }),
+            "release" => Some(Self {
+                name: name.into(),
+                description: Some("Optimized build without debug info".into()),
+                opt_level: Some("3".into()),
+                debug: Some(false),
+                defines: vec!["NDEBUG".into()],
+                ..Default::default()
+            }),
+            _ => None,
+        }
+    }
+
+    fn from_toml(name: &str, value: &toml::Value) -> Self {
+        let strings = |key: &str| {
+            value
+                .get(key)
+                .and_then(toml::Value::as_array)
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let string = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+        Self {
+            name: name.into(),
+            inherits: string("inherits"),
+            description: string("description"),
+            opt_level: value.get("opt-level").map(|v| match v {
+                toml::Value::Integer(i) => i.to_string(),
+                other => other.as_str().unwrap_or_default().to_string(),
+            }),
+            debug: value.get("debug").and_then(toml::Value::as_bool),
+            defines: strings("defines"),
+            flags: strings("flags"),
+        }
+    }
+
+    // Layers `self` on top of `base`: scalars override, defines and flags accumulate
+    fn merged_over(self, base: Profile) -> Self {
+        Self {
+            name: self.name,
+            inherits: self.inherits,
+            description: self.description.or(base.description),
+            opt_level: self.opt_level.or(base.opt_level),
+            debug: self.debug.or(base.debug),
+            defines: base.defines.into_iter().chain(self.defines).collect(),
+            flags: base.flags.into_iter().chain(self.flags).collect(),
+        }
+    }
+}
+
+impl IntoLua for Profile {
+    fn into_lua(self, lua: &Lua) -> mlua::Result<Value> {
+        let table = lua.create_table()?;
+        table.set("name", self.name)?;
+        table.set("inherits", self.inherits)?;
+        table.set("opt_level", self.opt_level.unwrap_or_else(|| "0".into()))?;
+        table.set("debug", self.debug.unwrap_or(false))?;
+        table.set("defines", self.defines)?;
+        table.set("flags", self.flags)?;
+        Ok(Value::Table(table))
+    }
+}
+
+// Profiles declared in the `[profile.<name>]` sections plus the builtin ones
+#[derive(Debug)]
+pub struct Profiles {
+    declared: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    pub fn from_config(config: &toml::Value) -> Self {
+        let declared = config
+            .get("profile")
+            .and_then(toml::Value::as_table)
+            .map(|t| {
+                t.iter()
+                    .map(|(name, v)| (name.clone(), Profile::from_toml(name, v)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { declared }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = ["debug", "release"].map(String::from).to_vec();
+        for name in self.declared.keys() {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.declared.contains_key(name) || Profile::builtin(name).is_some()
+    }
+
+    // Resolves the inheritance chain of a profile, exits on unknown or cyclic profiles
+    pub fn resolve(&self, name: &str) -> Profile {
+        let mut chain: Vec<String> = Vec::new();
+        self.resolve_chain(name, &mut chain)
+    }
+
+    fn resolve_chain(&self, name: &str, chain: &mut Vec<String>) -> Profile {
+        if chain.iter().any(|n| n == name) {
+            chain.push(name.into());
+            fail(LiebeError::InvalidProfile(format!(
+                "inheritance cycle: {}",
+                chain.join(" -> ")
+            )));
+        }
+        if !self.contains(name) {
+            let names = self.names();
+            let hint = did_you_mean(name, names.iter().map(String::as_str))
+                .map(|s| format!(", did you mean `{s}`?"))
+                .unwrap_or_default();
+            fail(LiebeError::UnknownProfile(format!(
+                "`{name}` (available: {}){hint}",
+                names.join(", ")
+            )));
+        }
+        chain.push(name.into());
+        let builtin = Profile::builtin(name);
+        let Some(declared) = self.declared.get(name).cloned() else {
+            return builtin.unwrap_or_default();
+        };
+        let base = match &declared.inherits {
+            Some(parent) => self.resolve_chain(parent, chain),
+            None => builtin.unwrap_or_default(),
+        };
+        declared.merged_over(base)
+    }
+}
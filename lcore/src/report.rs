@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::diagnostics::{Diagnostic, Summary};
+use crate::runner::{Event, Runner, Task, TaskStatus};
+use crate::state::DirtyReason;
+use mlua::{Function, Lua, MultiValue, String as LuaString};
+use serde_json::{Value, json};
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+
+pub static mut JSON: bool = false;
+
+/*
+MESSAGE FORMAT
+With `--message-format=json`, build, run and test print what happens as
+JSON objects on stdout, one per line, for CI tools to read. The text meant
+for people goes to stderr instead, and so do the output of the programs
+sharing the terminal and what the lua scripts print. Every object names its
+event:
+    {"event": "task-started", "id": ..., "name": ..., "target": ..., "cmd": [...], "reason": {...}}
+    {"event": "task-skipped", "id": ..., "name": ..., "target": ...}
+    {"event": "task-finished", "id": ..., "name": ..., "target": ..., "success": ...,
+     "exit_code": ..., "timed_out": ..., "duration_ms": ..., "output": [...], "diagnostics": [...]}
+    {"event": "task-planned", ...} on `--dry-run`, like task-started
+    {"event": "diagnostic-summary", "errors": ..., "warnings": ..., "first_error": {...} | null,
+     "diagnostics": [{"severity": ..., "message": ..., "file": ..., "line": ..., "column": ...}]}
+    {"event": "build-finished", "success": ..., "tasks": ..., "ran": ..., "skipped": ..., "failed": ...}
+    {"event": "program-started", "cmd": [...]}
+    {"event": "program-finished", "cmd": [...], "exit_code": ..., "duration_ms": ...}
+    {"event": "test-finished", "name": ..., "outcome": ..., ...}
+    {"event": "test-summary", "passed": ..., "failed": ..., "timed_out": ...}
+*/
+
+pub fn set_json(val: bool) {
+    unsafe { JSON = val }
+}
+
+pub fn get_json() -> bool {
+    unsafe { JSON }
+}
+
+// Text for people, on stderr when stdout carries the events
+pub fn say(args: fmt::Arguments) {
+    if get_json() {
+        eprintln!("{args}");
+    } else {
+        println!("{args}");
+    }
+}
+
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::report::say(format_args!($($arg)*))
+    };
+}
+
+// `print` of the lua scripts when stdout carries the events, same as lua's but on stderr
+pub fn lua_print(lua: &Lua, args: MultiValue) -> Result<(), mlua::Error> {
+    let tostring: Function = lua.globals().get("tostring")?;
+    let mut line = Vec::new();
+    for arg in args {
+        line.push(tostring.call::<LuaString>(arg)?.to_string_lossy());
+    }
+    eprintln!("{}", line.join("\t"));
+    Ok(())
+}
+
+// Bold red on a terminal, unless NO_COLOR is set
+fn highlight(text: &str) -> String {
+    let terminal = if get_json() {
+        io::stderr().is_terminal()
+    } else {
+        io::stdout().is_terminal()
+    };
+    if terminal && env::var_os("NO_COLOR").is_none() {
+        format!("\x1b[1;31m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+// Prints an event with `--message-format=json`, nothing otherwise
+pub fn emit(event: &str, fields: Value) {
+    if !get_json() {
+        return;
+    }
+    let mut object = json!({ "event": event });
+    if let (Some(object), Value::Object(fields)) = (object.as_object_mut(), fields) {
+        object.extend(fields);
+    }
+    let _ = writeln!(io::stdout().lock(), "{object}");
+}
+
+fn task_fields(id: usize, task: &Task) -> Value {
+    json!({
+        "id": id,
+        "name": task.spec().name,
+        "target": task.spec().target,
+    })
+}
+
+fn millis(task: &Task) -> u128 {
+    task.duration().as_millis()
+}
+
+// How a process ended, for people
+pub fn exit_message(task: &Task) -> String {
+    let cmd = task.cmd().join(" ");
+    if !task.spawned() {
+        format!("Process `{cmd}` was not spawned")
+    } else if task.timed_out() {
+        format!(
+            "Process `{cmd}` timed out after {:.2}s",
+            task.duration().as_secs_f64()
+        )
+    } else {
+        format!(
+            "Process `{cmd}` exited with {}",
+            task.exit_code().unwrap_or_default()
+        )
+    }
+}
+
+// Tells about a task before it runs, or instead of running it on `--dry-run`
+pub fn planned(id: usize, task: &Task, reason: &DirtyReason, explain: bool) {
+    if explain {
+        say!("{}: {reason}", task.spec().name);
+    }
+    say!("Would run: {}", task.cmd().join(" "));
+    let mut fields = task_fields(id, task);
+    fields["cmd"] = json!(task.cmd());
+    fields["reason"] = reason.to_json();
+    emit("task-planned", fields);
+}
+
+// The default handler of the runner events, `explain` tells why each task runs
+pub fn printer(explain: bool) -> impl FnMut(&Event) + Send + 'static {
+    move |event| match *event {
+        Event::Started { id, task, reason } => {
+            if explain {
+                say!("{}: {reason}", task.spec().name);
+            }
+            say!("Spawning command: {}", task.cmd().join(" "));
+            let mut fields = task_fields(id, task);
+            fields["cmd"] = json!(task.cmd());
+            fields["reason"] = reason.to_json();
+            emit("task-started", fields);
+        }
+        Event::Skipped { id, task } => emit("task-skipped", task_fields(id, task)),
+        Event::Finished { id, task } => {
+            if !task.output().is_empty() {
+                say!("{}", task.output().join("\n"));
+            }
+            say!("{}", exit_message(task));
+            let mut fields = task_fields(id, task);
+            fields["success"] = json!(task.status() == TaskStatus::completed());
+            fields["exit_code"] = json!(task.exit_code());
+            fields["timed_out"] = json!(task.timed_out());
+            fields["duration_ms"] = json!(millis(task));
+            fields["output"] = json!(task.output());
+            fields["diagnostics"] = task.diagnostics().iter().map(Diagnostic::to_json).collect();
+            emit("task-finished", fields);
+        }
+    }
+}
+
+// How many errors and warnings the compilers reported, after the output of the tasks
+fn diagnostic_summary(tasks: &[Task]) {
+    let summary = Summary::of(tasks);
+    if summary.is_empty() {
+        return;
+    }
+    say!("{summary}");
+    if let Some(error) = summary.first_error() {
+        say!("First error: {}", highlight(&error.to_string()));
+    }
+    emit(
+        "diagnostic-summary",
+        json!({
+            "errors": summary.errors(),
+            "warnings": summary.warnings(),
+            "first_error": summary.first_error().map(Diagnostic::to_json),
+            "diagnostics": summary.diagnostics.iter().map(Diagnostic::to_json).collect::<Vec<_>>(),
+        }),
+    );
+}
+
+pub fn build_finished(runner: &Runner) {
+    let tasks = runner.tasks();
+    let success = runner.get_status() == TaskStatus::completed();
+    diagnostic_summary(tasks);
+    if !success {
+        eprintln!("Build failed, `liebe log --failed` prints the output of the failed tasks");
+    }
+    let skipped = tasks.iter().filter(|t| t.skipped()).count();
+    let failed = tasks
+        .iter()
+        .filter(|t| t.status() == TaskStatus::error())
+        .count();
+    let ran = tasks
+        .iter()
+        .filter(|t| !t.skipped() && t.status() != TaskStatus::waiting())
+        .count();
+    emit(
+        "build-finished",
+        json!({
+            "success": success,
+            "tasks": tasks.len(),
+            "ran": ran,
+            "skipped": skipped,
+            "failed": failed,
+        }),
+    );
+}
+
+// The program of `liebe run`, which shares the terminal
+pub fn program_started(task: &Task) {
+    say!("Spawning command: {}", task.cmd().join(" "));
+    emit("program-started", json!({ "cmd": task.cmd() }));
+}
+
+pub fn program_finished(task: &Task) {
+    // Only set when the program could not be spawned
+    if !task.output().is_empty() {
+        say!("{}", task.output().join("\n"));
+    }
+    say!("{}", exit_message(task));
+    emit(
+        "program-finished",
+        json!({
+            "cmd": task.cmd(),
+            "exit_code": task.exit_code(),
+            "duration_ms": millis(task),
+        }),
+    );
+}
+
+pub fn test_summary(passed: usize, failed: usize, timed_out: usize) {
+    emit(
+        "test-summary",
+        json!({
+            "passed": passed,
+            "failed": failed,
+            "timed_out": timed_out,
+        }),
+    );
+}
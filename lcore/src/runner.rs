@@ -1,41 +1,58 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 coppamocha
+use crate::cargo;
+use crate::depfile::{self, DepStyle};
+use crate::diagnostics::{self, Diagnostic, DiagnosticFormat};
 use crate::empty_err;
 use crate::error::{ExitOnError, LiebeError};
-use crate::slidingvec::SlidingVec;
-use std::io::{BufRead, BufReader};
+use crate::report;
+use crate::state::{self, DirtyReason, StateDb, TaskRecord};
+use crate::utils::json_strings;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::fs;
+use std::io::{self, BufRead, BufReader};
 use std::num::NonZero;
+use std::path::{Path, PathBuf};
 use std::process::ChildStdout;
 use std::process::{Child, ChildStderr, Command, Stdio, exit};
-use std::thread::{JoinHandle, sleep};
-use std::time::Duration;
+use std::thread::{self, JoinHandle, sleep};
+use std::time::{Duration, Instant};
 
 pub type CommandStr = Vec<String>;
 
-fn read_child_stdout_lines(stdout: Option<ChildStdout>) {
+// Thread collecting the lines of stdout or stderr of a child process
+type OutputReader = JoinHandle<Vec<String>>;
+
+// Pool of depth 1 whose tasks get the terminal, as in ninja
+pub const CONSOLE_POOL: &str = "console";
+
+fn read_child_stdout_lines(stdout: Option<ChildStdout>) -> Vec<String> {
     if stdout.is_none() {
-        return;
+        return Vec::new();
     }
-    let reader = BufReader::new(stdout.unwrap())
+    BufReader::new(stdout.unwrap())
         .lines()
-        .filter_map(Result::ok)
-        .collect::<Vec<String>>();
-    if !reader.is_empty() {
-        println!("{}", reader.join("\n"));
-    }
+        .map_while(Result::ok)
+        .collect::<Vec<String>>()
 }
 
-fn read_child_stderr_lines(stderr: Option<ChildStderr>) {
+fn read_child_stderr_lines(stderr: Option<ChildStderr>) -> Vec<String> {
     if stderr.is_none() {
-        return;
+        return Vec::new();
     }
-    let reader = BufReader::new(stderr.unwrap())
+    BufReader::new(stderr.unwrap())
         .lines()
-        .filter_map(Result::ok)
-        .collect::<Vec<String>>();
-    if !reader.is_empty() {
-        println!("{}", reader.join("\n"));
-    }
+        .map_while(Result::ok)
+        .collect::<Vec<String>>()
+}
+
+// What a task prints on stdout besides its regular output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    // JSON messages of `cargo build --message-format=json`
+    Cargo,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -65,49 +82,392 @@ impl TaskStatus {
     }
 }
 
+// What a task does, independent of its execution
+#[derive(Debug, Clone, Default)]
+pub struct TaskSpec {
+    pub name: String,
+    // Target the task was generated for
+    pub target: String,
+    pub cmd: CommandStr,
+    pub cwd: Option<String>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    // Indices of the tasks that have to complete first
+    pub deps: Vec<usize>,
+    // Tasks that have to complete first without their changes making this one dirty
+    pub order_deps: Vec<usize>,
+    // Where the task reports the inputs it discovered, eg. included headers
+    pub depfile: Option<DepStyle>,
+    // Environment variables set for the command
+    pub env: BTreeMap<String, String>,
+    // Pool limiting how many tasks like this one run at once
+    pub pool: Option<String>,
+    pub messages: Option<MessageFormat>,
+    // Errors and warnings of the compiler are read from the output
+    pub diagnostics: Option<DiagnosticFormat>,
+}
+
+fn json_indices(value: &Value) -> Vec<usize> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_u64().map(|i| i as usize))
+        .collect()
+}
+
+// JSON as sent by the daemon and printed by `liebe tasks --json`
+impl TaskSpec {
+    pub fn to_json(&self) -> Value {
+        let depfile = match &self.depfile {
+            Some(DepStyle::Make(path)) => json!({ "make": path }),
+            Some(DepStyle::Msvc) => json!("msvc"),
+            None => Value::Null,
+        };
+        json!({
+            "name": self.name,
+            "target": self.target,
+            "cmd": self.cmd,
+            "cwd": self.cwd,
+            "inputs": self.inputs,
+            "outputs": self.outputs,
+            "deps": self.deps,
+            "order_deps": self.order_deps,
+            "depfile": depfile,
+            "env": self.env,
+            "pool": self.pool,
+            "messages": self.messages.map(|_| "cargo"),
+            "diagnostics": self.diagnostics.map(|d| d.name()),
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Self {
+        let string = |key: &str| value[key].as_str().map(String::from);
+        let depfile = match &value["depfile"] {
+            Value::String(s) if s == "msvc" => Some(DepStyle::Msvc),
+            v => v["make"].as_str().map(|p| DepStyle::Make(p.to_string())),
+        };
+        Self {
+            name: string("name").unwrap_or_default(),
+            target: string("target").unwrap_or_default(),
+            cmd: json_strings(&value["cmd"]),
+            cwd: string("cwd"),
+            inputs: json_strings(&value["inputs"]),
+            outputs: json_strings(&value["outputs"]),
+            deps: json_indices(&value["deps"]),
+            order_deps: json_indices(&value["order_deps"]),
+            depfile,
+            env: value["env"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                .collect(),
+            pool: string("pool"),
+            messages: string("messages").map(|_| MessageFormat::Cargo),
+            diagnostics: string("diagnostics").and_then(|d| DiagnosticFormat::parse(&d)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Task {
     proc: Option<Child>,
     status: TaskStatus,
     non_fatal: bool,
-    cmd: CommandStr,
+    interactive: bool,
+    exit_code: Option<i32>,
+    // Up to date, completed without running
+    skipped: bool,
+    discovered: Vec<String>,
+    // Files the task reported as built, from its messages
+    artifacts: Vec<String>,
+    // Declared outputs it wrote elsewhere, to the artifact of the same name
+    moved: BTreeMap<String, String>,
+    // Outputs of other tasks written elsewhere, replaced in its command
+    renames: HashMap<String, String>,
+    // Collect stdout and stderr while the task runs, so it never blocks on a full pipe
+    readers: Option<(OutputReader, OutputReader)>,
+    // Stdout and stderr of the task, reported once it finished
+    output: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+    timeout: Option<Duration>,
+    timed_out: bool,
+    started: Option<Instant>,
+    duration: Duration,
+    spec: TaskSpec,
 }
 
 impl Task {
     pub fn new(cmd: CommandStr) -> Self {
+        Self::from_spec(TaskSpec {
+            name: cmd.join(" "),
+            cmd,
+            ..Default::default()
+        })
+    }
+    pub fn from_spec(spec: TaskSpec) -> Self {
         Self {
             proc: None,
-            cmd,
+            spec,
             non_fatal: false,
-            status: TaskStatus::running(),
+            interactive: false,
+            exit_code: None,
+            skipped: false,
+            discovered: Vec::new(),
+            artifacts: Vec::new(),
+            moved: BTreeMap::new(),
+            renames: HashMap::new(),
+            readers: None,
+            output: Vec::new(),
+            diagnostics: Vec::new(),
+            timeout: None,
+            timed_out: false,
+            started: None,
+            duration: Duration::ZERO,
+            status: TaskStatus::waiting(),
+        }
+    }
+    pub fn spec(&self) -> &TaskSpec {
+        &self.spec
+    }
+    // The command as spawned, with the outputs of other tasks where they really are
+    pub fn cmd(&self) -> CommandStr {
+        self.spec
+            .cmd
+            .iter()
+            .map(|arg| {
+                self.renames
+                    .iter()
+                    .fold(arg.clone(), |arg, (from, to)| arg.replace(from, to))
+            })
+            .collect()
+    }
+    // Failure of the task is reported through its status instead of exiting
+    pub fn non_fatal(mut self) -> Self {
+        self.non_fatal = true;
+        self
+    }
+    // The task shares liebe's stdin/stdout/stderr instead of having its output collected
+    pub fn interactive(mut self) -> Self {
+        self.interactive = true;
+        self
+    }
+    // A task still running after `timeout` is killed and fails
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    pub fn status(&self) -> TaskStatus {
+        self.status
+    }
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+    // How long the task ran, up to now while it is running
+    pub fn duration(&self) -> Duration {
+        match (self.status == TaskStatus::running(), self.started) {
+            (true, Some(started)) => started.elapsed(),
+            _ => self.duration,
+        }
+    }
+    pub fn skipped(&self) -> bool {
+        self.skipped
+    }
+    // False once run if its program could not be spawned
+    pub fn spawned(&self) -> bool {
+        self.started.is_some()
+    }
+    fn skip(&mut self) {
+        self.skipped = true;
+        self.status = TaskStatus::completed();
+    }
+    // Relative paths reported by the task are taken from its working directory
+    fn absolute(&self, path: &str) -> String {
+        match &self.spec.cwd {
+            Some(cwd) => Path::new(cwd).join(path).display().to_string(),
+            None => path.to_string(),
+        }
+    }
+    fn collect_stdout(&mut self, lines: Vec<String>) {
+        let lines = if self.spec.depfile == Some(DepStyle::Msvc) {
+            let output = lines.join("\n");
+            let (deps, rest) = depfile::parse_msvc(&output);
+            self.discovered = deps.iter().map(|d| self.absolute(d)).collect();
+            rest.into_iter().map(String::from).collect()
+        } else if self.spec.messages == Some(MessageFormat::Cargo) {
+            let messages = cargo::parse_messages(&lines);
+            self.artifacts = messages.artifacts;
+            if self.spec.diagnostics == Some(DiagnosticFormat::Rustc) {
+                self.add_diagnostics(messages.diagnostics);
+            }
+            messages.lines
+        } else {
+            lines
+        };
+        self.output.extend(lines);
+    }
+    fn collect_stderr(&mut self, lines: Vec<String>) {
+        let lines = match self.spec.diagnostics {
+            Some(DiagnosticFormat::Gcc) => {
+                self.add_diagnostics(diagnostics::parse_gcc(&lines));
+                lines
+            }
+            Some(DiagnosticFormat::Rustc) => {
+                let (found, rest) = diagnostics::parse_rustc(lines);
+                self.add_diagnostics(found);
+                rest
+            }
+            None => lines,
+        };
+        self.output.extend(lines);
+    }
+    fn add_diagnostics(&mut self, found: Vec<Diagnostic>) {
+        for mut diagnostic in found {
+            diagnostic.file = diagnostic.file.map(|f| self.absolute(&f));
+            self.diagnostics.push(diagnostic);
+        }
+    }
+    /*
+    Where the task wrote its declared outputs, for tasks reporting their
+    artifacts. An output it reported under another directory, eg. because of
+    a `--target` triple or the target dir set in a cargo config, is taken
+    from there. A declared output it did not produce at all fails the task,
+    the list of what it built instead usually tells what is wrong with the
+    declared path.
+    */
+    fn locate_artifacts(&mut self) -> bool {
+        if self.spec.messages.is_none() {
+            return true;
+        }
+        let mut found = true;
+        for output in &self.spec.outputs {
+            if self.artifacts.contains(output) {
+                continue;
+            }
+            let name = Path::new(output).file_name();
+            match self
+                .artifacts
+                .iter()
+                .find(|a| Path::new(a).file_name() == name)
+            {
+                Some(actual) => {
+                    self.moved.insert(output.clone(), actual.clone());
+                }
+                None if Path::new(output).exists() => {}
+                None => {
+                    eprintln!(
+                        "`{}` did not produce `{output}`, it built:\n    {}",
+                        self.spec.name,
+                        self.artifacts.join("\n    ")
+                    );
+                    found = false;
+                }
+            }
+        }
+        found
+    }
+    // The depfile is written next to the outputs, wherever the task wrote them
+    fn depfile_path(&self) -> Option<PathBuf> {
+        let Some(DepStyle::Make(path)) = &self.spec.depfile else {
+            return None;
+        };
+        let path = PathBuf::from(self.absolute(path));
+        match (self.moved.values().next(), path.file_name()) {
+            (Some(actual), Some(name)) if !path.exists() => {
+                Some(Path::new(actual).with_file_name(name))
+            }
+            _ => Some(path),
         }
     }
+    fn read_depfile(&mut self) {
+        let Some(path) = self.depfile_path() else {
+            return;
+        };
+        // Without a depfile the task is simply rebuilt on the next change of its declared inputs
+        let Ok(contents) = fs::read_to_string(path) else {
+            return;
+        };
+        self.discovered = depfile::parse_make(&contents)
+            .iter()
+            .map(|d| self.absolute(d))
+            .collect();
+    }
     pub fn run(&mut self) {
-        let cmd_str = self.cmd.join(" ");
-        println!("Spawning command: {}", cmd_str);
-        let proc = Command::new(self.cmd[0].clone())
-            .args(&self.cmd[1..])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .log(LiebeError::CantSpawnChildProc(&cmd_str));
+        let cmd = self.cmd();
+        let cmd_str = cmd.join(" ");
+        for output in &self.spec.outputs {
+            if let Some(parent) = Path::new(output).parent() {
+                fs::create_dir_all(parent).log(LiebeError::CannotWriteFile(output));
+            }
+        }
+        let shared = self.interactive || self.spec.pool.as_deref() == Some(CONSOLE_POOL);
+        let (stdout, stderr) = match shared {
+            // Stdout only carries the JSON events
+            true if report::get_json() => (Stdio::from(io::stderr()), Stdio::inherit()),
+            true => (Stdio::inherit(), Stdio::inherit()),
+            false => (Stdio::piped(), Stdio::piped()),
+        };
+        let mut command = Command::new(&cmd[0]);
+        command
+            .args(&cmd[1..])
+            .envs(&self.spec.env)
+            .stdout(stdout)
+            .stderr(stderr);
+        if let Some(cwd) = &self.spec.cwd {
+            command.current_dir(cwd);
+        }
+        // A program that cannot be spawned fails like one exiting with an error
+        let mut proc = match command.spawn() {
+            Ok(proc) => proc,
+            Err(e) => {
+                self.output.push(format!("Cannot spawn `{cmd_str}`: {e}"));
+                self.status = TaskStatus::error();
+                return;
+            }
+        };
+        let stdout = proc.stdout.take();
+        let stderr = proc.stderr.take();
+        self.readers = Some((
+            thread::spawn(move || read_child_stdout_lines(stdout)),
+            thread::spawn(move || read_child_stderr_lines(stderr)),
+        ));
         self.status = TaskStatus::running();
+        self.started = Some(Instant::now());
         self.proc = Some(proc);
     }
     pub fn get_status(&mut self) -> TaskStatus {
-        if self.status != TaskStatus::running() {
+        if self.status != TaskStatus::running() || self.proc.is_none() {
+            return self.status;
+        }
+        if self.timeout.is_some_and(|t| self.duration() > t) {
+            self.duration = self.duration();
+            self.kill();
+            self.timed_out = true;
             return self.status;
         }
         self.status = match self.proc.as_mut().unwrap().try_wait() {
             Ok(Some(code)) => {
-                read_child_stdout_lines(self.proc.as_mut().unwrap().stdout.take());
-                read_child_stderr_lines(self.proc.as_mut().unwrap().stderr.take());
-                println!(
-                    "Process `{}` exited with {}",
-                    self.cmd.join(" "),
-                    code.code().unwrap_or_default()
-                );
-                if code.success() {
+                self.duration = self.duration();
+                let (stdout, stderr) = self
+                    .readers
+                    .take()
+                    .map(|(out, err)| (out.join(), err.join()))
+                    .unwrap_or((Ok(Vec::new()), Ok(Vec::new())));
+                self.collect_stdout(stdout.unwrap_or_default());
+                self.collect_stderr(stderr.unwrap_or_default());
+                self.exit_code = code.code();
+                if code.success() && self.locate_artifacts() {
+                    self.read_depfile();
                     TaskStatus::completed()
                 } else {
                     TaskStatus::error()
@@ -124,6 +484,21 @@ impl Task {
         };
         self.status
     }
+    // Stops the task if it is still running, keeping what it printed so far
+    pub fn kill(&mut self) {
+        if self.status != TaskStatus::running() {
+            return;
+        }
+        if let Some(proc) = self.proc.as_mut() {
+            let _ = proc.kill();
+            let _ = proc.wait();
+        }
+        if let Some((out, err)) = self.readers.take() {
+            self.output.extend(out.join().unwrap_or_default());
+            self.output.extend(err.join().unwrap_or_default());
+        }
+        self.status = TaskStatus::error();
+    }
     pub fn wait(&mut self) {
         while self.get_status() != TaskStatus::completed() {
             if self.status == TaskStatus::error() {
@@ -134,10 +509,44 @@ impl Task {
                     exit(1)
                 }
             }
+            sleep(Duration::from_millis(20));
         }
     }
 }
 
+// What happens to the tasks of a runner, in the order it happens
+pub enum Event<'a> {
+    Started {
+        id: usize,
+        task: &'a Task,
+        reason: &'a DirtyReason,
+    },
+    // Up to date, completed without running
+    Skipped {
+        id: usize,
+        task: &'a Task,
+    },
+    Finished {
+        id: usize,
+        task: &'a Task,
+    },
+}
+
+// Called from the thread of the runner for every event
+pub struct EventHandler(Box<dyn FnMut(&Event) + Send>);
+
+impl EventHandler {
+    fn call(&mut self, event: &Event) {
+        (self.0)(event)
+    }
+}
+
+impl Debug for EventHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventHandler")
+    }
+}
+
 pub struct RunnerHandle(Option<JoinHandle<Runner>>);
 
 impl RunnerHandle {
@@ -152,64 +561,245 @@ impl RunnerHandle {
 
 impl Drop for RunnerHandle {
     fn drop(&mut self) {
-        match self.0.take() {
-            Some(handle) => {
-                handle.join().log(empty_err!(ThreadFailedToJoin));
-            }
-            _ => {}
+        if let Some(handle) = self.0.take() {
+            handle.join().log(empty_err!(ThreadFailedToJoin));
         }
     }
 }
 
 #[derive(Debug)]
 pub struct Runner {
-    tasks: SlidingVec<Task>,
+    tasks: Vec<Task>,
+    // Without a state database every task runs
+    state: Option<StateDb>,
+    // Why each task has to run, `None` for the up to date ones
+    plan: Vec<Option<DirtyReason>>,
+    // Plan computed elsewhere, eg. by the daemon
+    preset: Option<Vec<Option<DirtyReason>>>,
+    handler: EventHandler,
+    // Depth of the pools declared with `liebe.pool`
+    pools: BTreeMap<String, usize>,
     pub status: TaskStatus,
     pub max_proc: usize,
 }
 
 type RunnerStatus = TaskStatus;
 
+impl Default for Runner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Runner {
     pub fn new() -> Self {
         Runner {
-            tasks: SlidingVec::new(),
+            tasks: Vec::new(),
+            state: None,
+            plan: Vec::new(),
+            preset: None,
+            handler: EventHandler(Box::new(report::printer(false))),
+            pools: BTreeMap::new(),
             max_proc: std::thread::available_parallelism()
                 .unwrap_or(NonZero::new(1).unwrap())
                 .into(),
             status: RunnerStatus::waiting(),
         }
     }
-    pub fn add_task(&mut self, task: Task) {
+    // Returns the index other tasks use to depend on this one
+    pub fn add_task(&mut self, task: Task) -> usize {
         self.tasks.push(task);
+        self.tasks.len() - 1
+    }
+    pub fn with_state(mut self, state: StateDb) -> Self {
+        for task in &mut self.tasks {
+            task.renames = state.moved().clone();
+        }
+        self.state = Some(state);
+        self
+    }
+    pub fn with_plan(mut self, plan: Vec<Option<DirtyReason>>) -> Self {
+        self.preset = Some(plan);
+        self
+    }
+    pub fn with_pools(mut self, pools: BTreeMap<String, usize>) -> Self {
+        self.pools = pools;
+        self
+    }
+    // Whether the pool of a task has room for one more among the running ones
+    fn pool_has_room(&self, id: usize, running: &[usize]) -> bool {
+        let Some(pool) = &self.tasks[id].spec.pool else {
+            return true;
+        };
+        let depth = match pool.as_str() {
+            CONSOLE_POOL => 1,
+            name => self.pools.get(name).copied().unwrap_or(usize::MAX),
+        };
+        running
+            .iter()
+            .filter(|&&r| self.tasks[r].spec.pool.as_ref() == Some(pool))
+            .count()
+            < depth
+    }
+    // Replaces printing what happens to the tasks
+    pub fn on_event(mut self, handler: impl FnMut(&Event) + Send + 'static) -> Self {
+        self.handler = EventHandler(Box::new(handler));
+        self
+    }
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+    /*
+    PLANNING
+    Decides which tasks have to run before any of them does. Tasks come in
+    dependency order, so a task is known to be dirty when one of its
+    dependencies or the producer of one of its inputs is, without waiting for
+    them to run. The rest is up to the state database.
+    */
+    pub fn plan(&self) -> Vec<Option<DirtyReason>> {
+        if let Some(plan) = self.preset.as_ref().filter(|p| p.len() == self.tasks.len()) {
+            return plan.clone();
+        }
+        let mut plan: Vec<Option<DirtyReason>> = Vec::with_capacity(self.tasks.len());
+        let mut rebuilt: Vec<&String> = Vec::new();
+        for task in &self.tasks {
+            let spec = &task.spec;
+            let reason = match &self.state {
+                None => Some(DirtyReason::NotRecorded),
+                Some(_)
+                    if spec
+                        .deps
+                        .iter()
+                        .any(|&d| plan.get(d).is_some_and(Option::is_some)) =>
+                {
+                    Some(DirtyReason::DependencyRebuilt)
+                }
+                Some(state) => match spec.inputs.iter().find(|i| rebuilt.contains(i)) {
+                    Some(input) => Some(DirtyReason::InputRebuilt(input.clone())),
+                    None => state.dirty(spec),
+                },
+            };
+            if reason.is_some() {
+                rebuilt.extend(&spec.outputs);
+            }
+            plan.push(reason);
+        }
+        plan
     }
+    // Remembers how a finished task ran, so the next build can skip it
+    fn record(&mut self, id: usize) {
+        let task = &self.tasks[id];
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        if task.status == TaskStatus::completed() {
+            let mut produced: Vec<String> = task
+                .spec
+                .outputs
+                .iter()
+                .map(|o| task.moved.get(o).unwrap_or(o).clone())
+                .collect();
+            produced.extend(task.depfile_path().map(|p| p.display().to_string()));
+            let record = TaskRecord {
+                cmd: task.spec.cmd.join(" "),
+                env: state::env_list(&task.spec),
+                discovered: task.discovered.clone(),
+                target: task.spec.target.clone(),
+                produced,
+                moved: task.moved.clone(),
+            };
+            state.set_record(&task.spec, record);
+        } else {
+            state.forget(&task.spec);
+        }
+    }
+    fn is_ready(&self, id: usize) -> bool {
+        let spec = &self.tasks[id].spec;
+        spec.deps
+            .iter()
+            .chain(&spec.order_deps)
+            .all(|&dep| self.tasks[dep].status == TaskStatus::completed())
+    }
+    /*
+    Tasks are started as soon as all of their dependencies completed, with at most
+    `max_proc` of them running at once. After a fatal failure no new task is started
+    and the runner waits for the running ones before giving up.
+    */
     fn run_sync(&mut self) {
         self.status = RunnerStatus::running();
-        self.tasks.window_right(self.max_proc, |tasks| {
-            for task in tasks.iter_mut() {
-                task.run();
+        self.plan = self.plan();
+        let mut running: Vec<usize> = Vec::new();
+        let mut failed = false;
+        loop {
+            if !failed {
+                for id in 0..self.tasks.len() {
+                    if running.len() >= self.max_proc {
+                        break;
+                    }
+                    if self.tasks[id].status == TaskStatus::waiting()
+                        && self.is_ready(id)
+                        && self.pool_has_room(id, &running)
+                    {
+                        let Some(reason) = &self.plan[id] else {
+                            self.tasks[id].skip();
+                            let task = &self.tasks[id];
+                            self.handler.call(&Event::Skipped { id, task });
+                            continue;
+                        };
+                        // Including what the tasks that ran before it moved
+                        if let Some(state) = &self.state {
+                            self.tasks[id].renames = state.moved().clone();
+                        }
+                        let task = &self.tasks[id];
+                        self.handler.call(&Event::Started { id, task, reason });
+                        self.tasks[id].run();
+                        running.push(id);
+                    }
+                }
             }
 
-            while !tasks.is_empty() {
-                let all_done = tasks.iter_mut().all(|t| {
-                    t.get_status() == TaskStatus::completed()
-                        || t.non_fatal && t.status == TaskStatus::error()
-                });
-                let has_errored = tasks
-                    .iter_mut()
-                    .all(|t| !t.non_fatal && t.status == TaskStatus::error());
-                if all_done {
-                    self.status = RunnerStatus::completed();
-                    break;
-                }
-                if has_errored {
-                    self.status = RunnerStatus::error();
-                    break;
+            let mut finished = Vec::new();
+            running.retain(|&id| {
+                let task = &mut self.tasks[id];
+                match task.get_status() {
+                    s if s == TaskStatus::running() => true,
+                    s if s == TaskStatus::error() => {
+                        failed |= !task.non_fatal;
+                        finished.push(id);
+                        false
+                    }
+                    _ => {
+                        finished.push(id);
+                        false
+                    }
                 }
-                sleep(Duration::from_millis(20));
+            });
+            for id in finished {
+                self.record(id);
+                let task = &self.tasks[id];
+                self.handler.call(&Event::Finished { id, task });
+            }
+            if running.is_empty() && (failed || !self.has_ready_tasks()) {
+                break;
             }
+            sleep(Duration::from_millis(20));
+        }
+        let all_done = self.tasks.iter().all(|t| {
+            t.status == TaskStatus::completed() || t.non_fatal && t.status == TaskStatus::error()
         });
-        self.tasks.pop_n(self.tasks.iter().len());
+        self.status = if all_done {
+            RunnerStatus::completed()
+        } else {
+            RunnerStatus::error()
+        };
+        if let Some(state) = &self.state {
+            state.save();
+        }
+    }
+
+    fn has_ready_tasks(&self) -> bool {
+        (0..self.tasks.len())
+            .any(|id| self.tasks[id].status == TaskStatus::waiting() && self.is_ready(id))
     }
 
     pub fn run(mut self) -> RunnerHandle {
@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::error::{ExitOnError, LiebeError, fail};
+use crate::workspace::{CACHE_DIR, CONFIG_FILE, DEFAULT_BUILD_DIR};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+SCAFFOLDING
+Templates of `liebe init`, compiled into the binary. Each language gets a
+liebe.toml, a hello world and whatever else it needs, eg. the lang-script
+in the `liebe/` script dir for languages without a bundled one. `{{name}}`
+is replaced by the name of the project, files starting with `#!` are made
+executable.
+*/
+type Template = &'static [(&'static str, &'static str)];
+
+const TEMPLATES: &[(&str, Template)] = &[
+    (
+        "c",
+        &[
+            (CONFIG_FILE, include_str!("../templates/c/liebe.toml.tmpl")),
+            ("src/main.c", include_str!("../templates/c/main.c.tmpl")),
+        ],
+    ),
+    (
+        "cpp",
+        &[
+            (
+                CONFIG_FILE,
+                include_str!("../templates/cpp/liebe.toml.tmpl"),
+            ),
+            (
+                "src/main.cpp",
+                include_str!("../templates/cpp/main.cpp.tmpl"),
+            ),
+        ],
+    ),
+    (
+        "rust",
+        &[
+            (
+                CONFIG_FILE,
+                include_str!("../templates/rust/liebe.toml.tmpl"),
+            ),
+            (
+                "Cargo.toml",
+                include_str!("../templates/rust/Cargo.toml.tmpl"),
+            ),
+            (
+                "src/main.rs",
+                include_str!("../templates/rust/main.rs.tmpl"),
+            ),
+        ],
+    ),
+    (
+        "go",
+        &[
+            (CONFIG_FILE, include_str!("../templates/go/liebe.toml.tmpl")),
+            ("liebe/go.lua", include_str!("../templates/go/go.lua.tmpl")),
+            ("go.mod", include_str!("../templates/go/go.mod.tmpl")),
+            ("main.go", include_str!("../templates/go/main.go.tmpl")),
+        ],
+    ),
+    (
+        "custom",
+        &[
+            (
+                CONFIG_FILE,
+                include_str!("../templates/custom/liebe.toml.tmpl"),
+            ),
+            (
+                "liebe/custom.lua",
+                include_str!("../templates/custom/custom.lua.tmpl"),
+            ),
+            (
+                "src/hello.sh",
+                include_str!("../templates/custom/hello.sh.tmpl"),
+            ),
+        ],
+    ),
+];
+
+// Directory for lang-scripts and lua modules of the project, see `SEARCH_DIRS`
+const SCRIPT_DIR: &str = "liebe";
+
+const GITIGNORE: &str = ".gitignore";
+
+pub fn languages() -> Vec<&'static str> {
+    TEMPLATES.iter().map(|(lang, _)| *lang).collect()
+}
+
+// `name` made usable as a target, crate and module name
+pub fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        Some(_) => format!("app_{name}"),
+        None => "app".into(),
+    }
+}
+
+// Name of a project in `dir`
+pub fn project_name(dir: &Path) -> String {
+    let name = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    sanitize_name(&name)
+}
+
+// Entries of the build dir and the cache dir missing from the lines of a .gitignore
+fn gitignore_entries(existing: &str) -> Vec<String> {
+    [format!("/{DEFAULT_BUILD_DIR}/"), format!("/{CACHE_DIR}/")]
+        .into_iter()
+        .filter(|entry| {
+            let bare = entry.trim_matches('/');
+            !existing.lines().any(|l| l.trim().trim_matches('/') == bare)
+        })
+        .collect()
+}
+
+/*
+Writes the template of `lang` to `dir` and returns the files it wrote.
+Nothing is written when any of the files exists already, .gitignore only
+gets the entries it lacks.
+*/
+pub fn init(dir: &Path, lang: &str, name: &str) -> Vec<PathBuf> {
+    let Some((_, template)) = TEMPLATES.iter().find(|(l, _)| *l == lang) else {
+        fail(LiebeError::InvalidOption(format!(
+            "no template for `{lang}`, pick one of {}",
+            languages().join(", ")
+        )));
+    };
+    let existing: Vec<String> = template
+        .iter()
+        .map(|(path, _)| dir.join(path))
+        .filter(|path| path.exists())
+        .map(|path| path.display().to_string())
+        .collect();
+    if !existing.is_empty() {
+        fail(LiebeError::CannotWriteFile(format!(
+            "refusing to overwrite {}",
+            existing.join(", ")
+        )));
+    }
+
+    let mut written = Vec::new();
+    for (path, contents) in template.iter() {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .log(LiebeError::CannotWriteFile(parent.display().to_string()));
+        }
+        fs::write(&path, contents.replace("{{name}}", name))
+            .log(LiebeError::CannotWriteFile(path.display().to_string()));
+        if contents.starts_with("#!") {
+            set_executable(&path);
+        }
+        written.push(path);
+    }
+    let scripts = dir.join(SCRIPT_DIR);
+    fs::create_dir_all(&scripts).log(LiebeError::CannotWriteFile(scripts.display().to_string()));
+
+    let gitignore = dir.join(GITIGNORE);
+    let mut contents = fs::read_to_string(&gitignore).unwrap_or_default();
+    let entries = gitignore_entries(&contents);
+    if !entries.is_empty() {
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        contents.push_str(&entries.join("\n"));
+        contents.push('\n');
+        fs::write(&gitignore, contents)
+            .log(LiebeError::CannotWriteFile(gitignore.display().to_string()));
+        written.push(gitignore);
+    }
+    written
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+        .log(LiebeError::CannotWriteFile(path.display().to_string()));
+}
+
+#[cfg(not(unix))]
+fn set_executable(_: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_valid_target_names() {
+        assert_eq!(sanitize_name("hello"), "hello");
+        assert_eq!(sanitize_name("my app"), "my_app");
+        assert_eq!(sanitize_name("a\"]\nb"), "a___b");
+        assert_eq!(sanitize_name("2d-engine"), "app_2d-engine");
+        assert_eq!(sanitize_name(""), "app");
+        assert_eq!(project_name(Path::new("/src/my.lib")), "my_lib");
+    }
+}
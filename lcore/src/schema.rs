@@ -0,0 +1,359 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::options::options_schema;
+use crate::profile::profile_schema;
+use crate::target::targets_schema;
+use crate::utils::did_you_mean;
+use crate::workspace::workspace_schema;
+use mlua::{FromLuaMulti, Lua, MultiValue, Table, Value};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub schema: Schema,
+    pub required: bool,
+}
+
+impl Field {
+    pub fn new(name: &str, schema: Schema) -> Self {
+        Self {
+            name: name.to_string(),
+            schema,
+            required: false,
+        }
+    }
+    pub fn required(name: &str, schema: Schema) -> Self {
+        Self {
+            name: name.to_string(),
+            schema,
+            required: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Schema {
+    Any,
+    String,
+    Bool,
+    Integer,
+    // Integers are accepted where a float is expected
+    Float,
+    Array(Box<Schema>),
+    // Table with a fixed set of known keys
+    Table(Vec<Field>),
+    // Table with arbitrary keys whose values all share one schema
+    Map(Box<Schema>),
+    // String restricted to a set of values
+    Enum(Vec<String>),
+    // Value matching any of the given schemas
+    Either(Vec<Schema>),
+}
+
+impl Schema {
+    pub fn array(item: Schema) -> Self {
+        Self::Array(Box::new(item))
+    }
+
+    pub fn map(value: Schema) -> Self {
+        Self::Map(Box::new(value))
+    }
+
+    pub fn strings() -> Self {
+        Self::array(Self::String)
+    }
+
+    pub fn one_of(values: &[&str]) -> Self {
+        Self::Enum(values.iter().map(|v| v.to_string()).collect())
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Any => "any value".into(),
+            Self::String => "a string".into(),
+            Self::Bool => "a boolean".into(),
+            Self::Integer => "an integer".into(),
+            Self::Float => "a number".into(),
+            Self::Array(item) => format!("an array of {}", item.describe_plural()),
+            Self::Table(_) | Self::Map(_) => "a table".into(),
+            Self::Enum(values) => format!("one of {}", quote_list(values)),
+            Self::Either(schemas) => schemas
+                .iter()
+                .map(Self::describe)
+                .collect::<Vec<_>>()
+                .join(" or "),
+        }
+    }
+
+    fn describe_plural(&self) -> String {
+        match self {
+            Self::String => "strings".into(),
+            Self::Bool => "booleans".into(),
+            Self::Integer => "integers".into(),
+            Self::Float => "numbers".into(),
+            Self::Table(_) | Self::Map(_) => "tables".into(),
+            other => other.describe(),
+        }
+    }
+
+    /*
+    Parses a schema declared from lua. Accepted forms:
+        "string" | "bool" | "integer" | "float" | "any"
+        { type = "array", items = <schema> }
+        { type = "map", values = <schema> }
+        { type = "enum", values = { "a", "b" } }
+        { type = "table", fields = { key = <schema> }, required = { "key" } }
+        { type = "either", of = { <schema>, <schema> } }
+    A table without a `type` key is shorthand for { type = "table", fields = ... }
+    */
+    pub fn from_lua(value: Value) -> Result<Self, String> {
+        match value {
+            Value::String(s) => Self::from_name(&s.to_str().map_err(|e| e.to_string())?),
+            Value::Table(t) => Self::from_lua_table(t),
+            other => Err(format!(
+                "expected a type name or a table, got {}",
+                other.type_name()
+            )),
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "any" => Ok(Self::Any),
+            "string" => Ok(Self::String),
+            "bool" | "boolean" => Ok(Self::Bool),
+            "integer" | "int" => Ok(Self::Integer),
+            "float" | "number" => Ok(Self::Float),
+            "table" => Ok(Self::map(Self::Any)),
+            other => Err(format!("unknown schema type `{other}`")),
+        }
+    }
+
+    fn from_lua_table(table: Table) -> Result<Self, String> {
+        let kind: Option<String> = table.get("type").map_err(|e| e.to_string())?;
+        let field = |name: &str| -> Result<Value, String> {
+            table.get::<Value>(name).map_err(|e| e.to_string())
+        };
+        match kind.as_deref() {
+            Some("array") => Ok(Self::array(Self::from_lua(field("items")?)?)),
+            Some("map") => Ok(Self::map(Self::from_lua(field("values")?)?)),
+            Some("enum") => {
+                let values: Vec<String> = table.get("values").map_err(|e| e.to_string())?;
+                Ok(Self::Enum(values))
+            }
+            Some("either") => {
+                let of: Vec<Value> = table.get("of").map_err(|e| e.to_string())?;
+                Ok(Self::Either(
+                    of.into_iter()
+                        .map(Self::from_lua)
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            Some("table") => Self::fields_from_lua(
+                table.get("fields").map_err(|e| e.to_string())?,
+                table.get("required").map_err(|e| e.to_string())?,
+            ),
+            Some(name) => Self::from_name(name),
+            None => Self::fields_from_lua(table, None),
+        }
+    }
+
+    fn fields_from_lua(fields: Table, required: Option<Vec<String>>) -> Result<Self, String> {
+        let required = required.unwrap_or_default();
+        let mut out = Vec::new();
+        for pair in fields.pairs::<String, Value>() {
+            let (name, value) = pair.map_err(|e| e.to_string())?;
+            let schema = Self::from_lua(value).map_err(|e| format!("{name}: {e}"))?;
+            out.push(Field {
+                required: required.contains(&name),
+                name,
+                schema,
+            });
+        }
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self::Table(out))
+    }
+
+    fn matches(&self, value: &toml::Value) -> bool {
+        let mut errors = Vec::new();
+        self.check(value, "", &mut errors);
+        errors.is_empty()
+    }
+
+    fn check(&self, value: &toml::Value, path: &str, errors: &mut Vec<SchemaError>) {
+        use toml::Value as V;
+        let mismatch = |errors: &mut Vec<SchemaError>| {
+            errors.push(SchemaError::new(
+                path,
+                format!("expected {}, found {}", self.describe(), value.type_str()),
+            ))
+        };
+        match (self, value) {
+            (Self::Any, _)
+            | (Self::String, V::String(_))
+            | (Self::Bool, V::Boolean(_))
+            | (Self::Integer, V::Integer(_))
+            | (Self::Float, V::Float(_) | V::Integer(_)) => {}
+            (Self::Array(item), V::Array(values)) => {
+                for (i, v) in values.iter().enumerate() {
+                    item.check(v, &format!("{path}[{i}]"), errors);
+                }
+            }
+            (Self::Map(inner), V::Table(table)) => {
+                for (key, v) in table {
+                    inner.check(v, &join_path(path, key), errors);
+                }
+            }
+            (Self::Table(fields), V::Table(table)) => {
+                check_fields(fields, table, path, errors);
+            }
+            (Self::Enum(values), V::String(s)) => {
+                if !values.contains(s) {
+                    let mut err = SchemaError::new(
+                        path,
+                        format!(
+                            "invalid value `{s}`, expected one of {}",
+                            quote_list(values)
+                        ),
+                    );
+                    err.suggestion = did_you_mean(s, values.iter().map(String::as_str));
+                    errors.push(err);
+                }
+            }
+            (Self::Either(schemas), _) => {
+                if !schemas.iter().any(|s| s.matches(value)) {
+                    mismatch(errors);
+                }
+            }
+            _ => mismatch(errors),
+        }
+    }
+}
+
+fn check_fields(fields: &[Field], table: &toml::Table, path: &str, errors: &mut Vec<SchemaError>) {
+    for field in fields {
+        if field.required && !table.contains_key(&field.name) {
+            errors.push(SchemaError::new(
+                &join_path(path, &field.name),
+                "missing required key".into(),
+            ));
+        }
+    }
+    for (key, value) in table {
+        let key_path = join_path(path, key);
+        match fields.iter().find(|f| &f.name == key) {
+            Some(field) => field.schema.check(value, &key_path, errors),
+            None => {
+                let mut err = SchemaError::new(&key_path, "unknown key".into());
+                err.suggestion = did_you_mean(key, fields.iter().map(|f| f.name.as_str()));
+                errors.push(err);
+            }
+        }
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn quote_list(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|v| format!("`{v}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl SchemaError {
+    fn new(path: &str, message: String) -> Self {
+        Self {
+            path: path.to_string(),
+            message,
+            suggestion: None,
+        }
+    }
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.path, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        Ok(())
+    }
+}
+
+// Schema of the keys understood by the core, lang-scripts extend it with their own sections
+pub fn core_schema() -> Vec<Field> {
+    vec![
+        Field::required("lang-script", Schema::String),
+        Field::new(
+            "project",
+            Schema::Table(vec![
+                Field::new("name", Schema::String),
+                Field::new("version", Schema::String),
+                Field::new("description", Schema::String),
+                Field::new("authors", Schema::strings()),
+                Field::new("license", Schema::String),
+                Field::new("build-dir", Schema::String),
+            ]),
+        ),
+        Field::new("targets", targets_schema()),
+        Field::new("profile", profile_schema()),
+        Field::new("dependencies", Schema::map(Schema::Any)),
+        Field::new("options", options_schema()),
+        Field::new("workspace", workspace_schema()),
+    ]
+}
+
+// Registry of language specific top-level sections, filled by lang-scripts
+#[derive(Debug, Default)]
+pub struct SchemaExtensions(pub BTreeMap<String, Schema>);
+
+pub fn validate(config: &toml::Value, extensions: &SchemaExtensions) -> Vec<SchemaError> {
+    let mut fields = core_schema();
+    // A workspace root does not need to be a project itself
+    if config.get("workspace").is_some() {
+        fields.iter_mut().for_each(|f| f.required = false);
+    }
+    for (name, schema) in &extensions.0 {
+        fields.push(Field::new(name, schema.clone()));
+    }
+    let mut errors = Vec::new();
+    match config.as_table() {
+        Some(table) => check_fields(&fields, table, "", &mut errors),
+        None => errors.push(SchemaError::new("", "expected a table".into())),
+    }
+    errors
+}
+
+// liebe.schema(section, spec): lets a lang-script declare its own top-level section
+pub fn lua_extend_schema(lua: &Lua, args: MultiValue) -> Result<MultiValue, mlua::Error> {
+    let (section, spec) = <(String, Value)>::from_lua_multi(args, lua)?;
+    if core_schema().iter().any(|f| f.name == section) {
+        return Err(mlua::Error::runtime(format!(
+            "`{section}` is a reserved section"
+        )));
+    }
+    let schema =
+        Schema::from_lua(spec).map_err(|e| mlua::Error::runtime(format!("{section}: {e}")))?;
+    lua.app_data_mut::<SchemaExtensions>()
+        .ok_or_else(|| mlua::Error::runtime("schema registry is not available"))?
+        .0
+        .insert(section, schema);
+    Ok(MultiValue::new())
+}
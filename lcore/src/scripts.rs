@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+
+/*
+BUNDLED SCRIPTS
+Lang-scripts and lua modules compiled into the binary, so they are available
+without an install tree. Files with the same name in the search dirs take
+precedence, which allows overriding them per project.
+*/
+const BUNDLED: &[(&str, &str)] = &[
+    ("c.lua", include_str!("../scripts/c.lua")),
+    ("cpp.lua", include_str!("../scripts/cpp.lua")),
+    ("liebe/cc.lua", include_str!("../scripts/liebe/cc.lua")),
+    ("rust.lua", include_str!("../scripts/rust.lua")),
+];
+
+// Prefix of the chunk names of bundled scripts in lua error messages
+pub const BUNDLED_PREFIX: &str = "<bundled>/";
+
+pub fn bundled(path: &str) -> Option<&'static str> {
+    BUNDLED
+        .iter()
+        .find(|(name, _)| *name == path)
+        .map(|(_, source)| *source)
+}
@@ -93,7 +93,7 @@ impl<T> SlidingVec<T> {
     // Applies closure to sliding windows on the right partition
     pub fn window_right<R, F: FnMut(&mut [T]) -> R>(&mut self, n: usize, mut closure: F) {
         let len = self.data.len() - self.top;
-        let no_groups = (len + n - 1) / n;
+        let no_groups = len.div_ceil(n);
 
         for i in 0..no_groups {
             let start = self.top + i * n;
@@ -108,6 +108,12 @@ impl<T> SlidingVec<T> {
     }
 }
 
+impl<T> Default for SlidingVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> IntoIterator for SlidingVec<T> {
     type Item = T;
     type IntoIter = std::vec::IntoIter<T>;
@@ -0,0 +1,404 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::empty_err;
+use crate::error::{ExitOnError, LiebeError};
+use crate::runner::TaskSpec;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const STATE_FILE: &str = "state.toml";
+
+// What was known about a task the last time it completed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaskRecord {
+    pub cmd: String,
+    // `NAME=value` pairs the command ran with
+    pub env: Vec<String>,
+    // Inputs found by the task itself, eg. headers listed in a depfile
+    pub discovered: Vec<String>,
+    // Label of the target the task belongs to
+    pub target: String,
+    // Files the task wrote: its outputs and its depfile, what `liebe clean` removes
+    pub produced: Vec<String>,
+    // Declared outputs the task wrote elsewhere, to the path it reported
+    pub moved: BTreeMap<String, String>,
+}
+
+// Why a task has to run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirtyReason {
+    NoOutputs,
+    NotRecorded,
+    MissingOutput(String),
+    MissingInput(String),
+    InputNewer(String),
+    InputRebuilt(String),
+    CommandChanged,
+    EnvChanged,
+    DependencyRebuilt,
+}
+
+impl Display for DirtyReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoOutputs => write!(f, "it declares no outputs"),
+            Self::NotRecorded => write!(f, "it never ran before"),
+            Self::MissingOutput(path) => write!(f, "output `{path}` is missing"),
+            Self::MissingInput(path) => write!(f, "input `{path}` is missing"),
+            Self::InputNewer(path) => write!(f, "input `{path}` is newer than its outputs"),
+            Self::InputRebuilt(path) => write!(f, "input `{path}` is rebuilt"),
+            Self::CommandChanged => write!(f, "its command line changed"),
+            Self::EnvChanged => write!(f, "its environment changed"),
+            Self::DependencyRebuilt => write!(f, "a task it depends on is rebuilt"),
+        }
+    }
+}
+
+// JSON as sent by the daemon and reported by `--message-format=json`
+impl DirtyReason {
+    pub fn to_json(&self) -> Value {
+        let (kind, path) = match self {
+            Self::NoOutputs => ("no-outputs", None),
+            Self::NotRecorded => ("not-recorded", None),
+            Self::MissingOutput(p) => ("missing-output", Some(p)),
+            Self::MissingInput(p) => ("missing-input", Some(p)),
+            Self::InputNewer(p) => ("input-newer", Some(p)),
+            Self::InputRebuilt(p) => ("input-rebuilt", Some(p)),
+            Self::CommandChanged => ("command-changed", None),
+            Self::EnvChanged => ("env-changed", None),
+            Self::DependencyRebuilt => ("dependency-rebuilt", None),
+        };
+        json!({ "reason": kind, "path": path })
+    }
+
+    // Unknown reasons make the task run, like a task that never ran
+    pub fn from_json(value: &Value) -> Option<Self> {
+        if value.is_null() {
+            return None;
+        }
+        let path = value["path"].as_str().unwrap_or_default().to_string();
+        Some(match value["reason"].as_str() {
+            Some("no-outputs") => Self::NoOutputs,
+            Some("missing-output") => Self::MissingOutput(path),
+            Some("missing-input") => Self::MissingInput(path),
+            Some("input-newer") => Self::InputNewer(path),
+            Some("input-rebuilt") => Self::InputRebuilt(path),
+            Some("command-changed") => Self::CommandChanged,
+            Some("env-changed") => Self::EnvChanged,
+            Some("dependency-rebuilt") => Self::DependencyRebuilt,
+            _ => Self::NotRecorded,
+        })
+    }
+}
+
+/*
+STATE DATABASE
+Kept in the cache dir of the workspace, it records for every task that
+completed the command it ran and the inputs it discovered. Tasks are keyed
+by their first output, which is an absolute path.
+*/
+#[derive(Debug, Default)]
+pub struct StateDb {
+    path: PathBuf,
+    tasks: BTreeMap<String, TaskRecord>,
+    // The `moved` outputs of all the records
+    moved: HashMap<String, String>,
+    changed: bool,
+    stats: Option<Arc<Mutex<StatCache>>>,
+}
+
+/*
+Modification times of source files kept by the daemon between builds. It
+watches those files and refreshes their entry when they change, the times
+of other files are read again every time.
+*/
+#[derive(Debug, Default)]
+pub struct StatCache {
+    times: HashMap<String, Option<SystemTime>>,
+}
+
+impl StatCache {
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.times.contains_key(path)
+    }
+
+    pub fn refresh(&mut self, path: &str) {
+        self.times.insert(path.to_string(), mtime(path));
+    }
+}
+
+pub fn env_list(spec: &TaskSpec) -> Vec<String> {
+    spec.env.iter().map(|(k, v)| format!("{k}={v}")).collect()
+}
+
+fn mtime(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl StateDb {
+    pub fn load(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(STATE_FILE);
+        let table: toml::Table = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default();
+        let strings = |v: Option<&toml::Value>| -> Vec<String> {
+            v.and_then(toml::Value::as_array)
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let tasks = table
+            .get("tasks")
+            .and_then(toml::Value::as_table)
+            .map(|t| {
+                t.iter()
+                    .map(|(key, v)| {
+                        let record = TaskRecord {
+                            cmd: v
+                                .get("cmd")
+                                .and_then(toml::Value::as_str)
+                                .unwrap_or_default()
+                                .to_string(),
+                            env: strings(v.get("env")),
+                            discovered: strings(v.get("discovered")),
+                            target: v
+                                .get("target")
+                                .and_then(toml::Value::as_str)
+                                .unwrap_or_default()
+                                .to_string(),
+                            produced: strings(v.get("produced")),
+                            moved: v
+                                .get("moved")
+                                .and_then(toml::Value::as_table)
+                                .into_iter()
+                                .flatten()
+                                .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                                .collect(),
+                        };
+                        (key.clone(), record)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let moved = Self::all_moved(&tasks);
+        Self {
+            path,
+            tasks,
+            moved,
+            changed: false,
+            stats: None,
+        }
+    }
+
+    pub fn with_stats(mut self, stats: Arc<Mutex<StatCache>>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    fn input_mtime(&self, path: &str) -> Option<SystemTime> {
+        let cached = self.stats.as_ref().and_then(|stats| {
+            let stats = stats.lock().ok()?;
+            stats.times.get(path).copied()
+        });
+        cached.unwrap_or_else(|| mtime(path))
+    }
+
+    pub fn save(&self) {
+        if !self.changed {
+            return;
+        }
+        let mut tasks = toml::Table::new();
+        for (key, record) in &self.tasks {
+            let mut entry = toml::Table::new();
+            entry.insert("cmd".into(), record.cmd.clone().into());
+            entry.insert("env".into(), record.env.clone().into());
+            entry.insert("discovered".into(), record.discovered.clone().into());
+            entry.insert("target".into(), record.target.clone().into());
+            entry.insert("produced".into(), record.produced.clone().into());
+            if !record.moved.is_empty() {
+                let moved: toml::Table = record
+                    .moved
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone().into()))
+                    .collect();
+                entry.insert("moved".into(), moved.into());
+            }
+            tasks.insert(key.clone(), entry.into());
+        }
+        let mut table = toml::Table::new();
+        table.insert("tasks".into(), tasks.into());
+
+        let path = self.path.display().to_string();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).log(LiebeError::CannotWriteFile(path.clone()));
+        }
+        let contents = toml::to_string(&table).log(empty_err!(InvalidConf));
+        fs::write(&self.path, contents).log(LiebeError::CannotWriteFile(path));
+    }
+
+    fn key(spec: &TaskSpec) -> Option<&String> {
+        spec.outputs.first()
+    }
+
+    pub fn record(&self, spec: &TaskSpec) -> Option<&TaskRecord> {
+        Self::key(spec).and_then(|key| self.tasks.get(key))
+    }
+
+    pub fn set_record(&mut self, spec: &TaskSpec, record: TaskRecord) {
+        if let Some(key) = Self::key(spec) {
+            self.changed |= self.tasks.get(key) != Some(&record);
+            let moved = !record.moved.is_empty();
+            let old = self.tasks.insert(key.clone(), record);
+            if moved || old.is_some_and(|old| !old.moved.is_empty()) {
+                self.moved = Self::all_moved(&self.tasks);
+            }
+        }
+    }
+
+    fn all_moved(tasks: &BTreeMap<String, TaskRecord>) -> HashMap<String, String> {
+        tasks.values().flat_map(|r| r.moved.clone()).collect()
+    }
+
+    // Declared outputs written elsewhere, to where they really are
+    pub fn moved(&self) -> &HashMap<String, String> {
+        &self.moved
+    }
+
+    // Where a declared output really is, when the task that wrote it reported another path
+    pub fn actual<'a>(&'a self, path: &'a str) -> &'a str {
+        self.moved.get(path).map_or(path, String::as_str)
+    }
+
+    // Records by the first output of their task
+    pub fn records(&self) -> impl Iterator<Item = (&String, &TaskRecord)> {
+        self.tasks.iter()
+    }
+
+    pub fn forget_key(&mut self, key: &str) {
+        if let Some(old) = self.tasks.remove(key) {
+            self.changed = true;
+            if !old.moved.is_empty() {
+                self.moved = Self::all_moved(&self.tasks);
+            }
+        }
+    }
+
+    pub fn forget(&mut self, spec: &TaskSpec) {
+        if let Some(key) = Self::key(spec) {
+            self.forget_key(&key.clone());
+        }
+    }
+
+    /*
+    A task is up to date when all of its outputs exist, it ran before with the
+    same command and environment, and none of its inputs, declared or
+    discovered, is newer than its oldest output. Tasks without outputs always
+    run. Outputs are looked for where their task reported writing them.
+    */
+    pub fn dirty(&self, spec: &TaskSpec) -> Option<DirtyReason> {
+        if spec.outputs.is_empty() {
+            return Some(DirtyReason::NoOutputs);
+        }
+        let mut oldest = None;
+        for output in &spec.outputs {
+            let Some(time) = mtime(self.actual(output)) else {
+                return Some(DirtyReason::MissingOutput(output.clone()));
+            };
+            oldest = Some(oldest.map_or(time, |o: SystemTime| o.min(time)));
+        }
+        let Some(record) = self.record(spec) else {
+            return Some(DirtyReason::NotRecorded);
+        };
+        if record.cmd != spec.cmd.join(" ") {
+            return Some(DirtyReason::CommandChanged);
+        }
+        if record.env != env_list(spec) {
+            return Some(DirtyReason::EnvChanged);
+        }
+        for input in spec.inputs.iter().chain(&record.discovered) {
+            match self.input_mtime(self.actual(input)) {
+                None => return Some(DirtyReason::MissingInput(input.clone())),
+                Some(time) if Some(time) > oldest => {
+                    return Some(DirtyReason::InputNewer(input.clone()));
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn moved_outputs_are_read_where_they_were_written() {
+        let dir = env::temp_dir().join(format!("liebe-moved-{}", process::id()));
+        let declared = dir.join("debug/libx.a").display().to_string();
+        let actual = dir.join("triple/debug/libx.a").display().to_string();
+        fs::create_dir_all(dir.join("triple/debug")).unwrap();
+        fs::write(&actual, "").unwrap();
+        let spec = TaskSpec {
+            cmd: vec!["cargo".into(), "build".into()],
+            outputs: vec![declared.clone()],
+            ..Default::default()
+        };
+        let link = TaskSpec {
+            cmd: vec!["cc".into(), declared.clone()],
+            inputs: vec![declared.clone()],
+            outputs: vec![dir.join("app").display().to_string()],
+            ..Default::default()
+        };
+
+        let mut state = StateDb::load(&dir);
+        assert_eq!(
+            state.dirty(&spec),
+            Some(DirtyReason::MissingOutput(declared.clone()))
+        );
+        let record = TaskRecord {
+            cmd: "cargo build".into(),
+            moved: BTreeMap::from([(declared.clone(), actual.clone())]),
+            ..Default::default()
+        };
+        state.set_record(&spec, record);
+        assert_eq!(state.dirty(&spec), None);
+        assert_eq!(state.actual(&declared), actual);
+        fs::write(dir.join("app"), "").unwrap();
+        state.set_record(
+            &link,
+            TaskRecord {
+                cmd: link.cmd.join(" "),
+                ..Default::default()
+            },
+        );
+        assert_eq!(state.dirty(&link), None);
+
+        // Saved and loaded again
+        state.save();
+        let mut state = StateDb::load(&dir);
+        assert_eq!(state.actual(&declared), actual);
+        state.forget(&spec);
+        assert_eq!(state.actual(&declared), declared);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,295 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::error::{LiebeError, fail};
+use crate::install;
+use crate::schema::{Field, Schema};
+use crate::utils::did_you_mean;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Name of the target implied when liebe.toml declares no `[targets]`
+const IMPLICIT_TARGET: &str = "main";
+
+pub fn targets_schema() -> Schema {
+    Schema::map(Schema::Table(vec![
+        Field::new(
+            "kind",
+            Schema::one_of(&["executable", "library", "test", "custom"]),
+        ),
+        Field::new("description", Schema::String),
+        Field::new("sources", Schema::strings()),
+        Field::new("deps", Schema::strings()),
+        Field::new("default", Schema::Bool),
+        Field::new("build", Schema::String),
+        Field::new("run", Schema::String),
+        Field::new("timeout", Schema::Integer),
+        Field::new("install", Schema::Bool),
+        Field::new(
+            "install-files",
+            Schema::array(Schema::Table(vec![
+                Field::required("files", Schema::strings()),
+                Field::required("dir", Schema::String),
+                Field::new("mode", Schema::String),
+            ])),
+        ),
+    ]))
+}
+
+// Files from the project directory a target installs besides what its lang-script installs
+#[derive(Debug, Clone)]
+pub struct InstallFiles {
+    // Patterns relative to the project directory
+    pub files: Vec<String>,
+    pub dir: String,
+    pub mode: Option<String>,
+}
+
+fn strings_of(value: &toml::Value, key: &str) -> Vec<String> {
+    value
+        .get(key)
+        .and_then(toml::Value::as_array)
+        .map(|a| {
+            a.iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Executable,
+    Library,
+    Test,
+    Custom,
+}
+
+impl TargetKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Executable => "executable",
+            Self::Library => "library",
+            Self::Test => "test",
+            Self::Custom => "custom",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub name: String,
+    pub kind: TargetKind,
+    pub description: Option<String>,
+    // Source patterns as written in liebe.toml, globs are expanded by `expand_sources`
+    pub sources: Vec<String>,
+    pub deps: Vec<String>,
+    pub default: bool,
+    // Names of the lua functions building and running the target
+    pub build_hook: String,
+    pub run_hook: String,
+    // Seconds a test may run before it fails
+    pub timeout: Option<u64>,
+    // Whether `liebe install` installs anything of the target
+    pub install: bool,
+    pub install_files: Vec<InstallFiles>,
+}
+
+// `dir` of an `install-files` entry, which has to stay under the prefix
+fn install_dir(target: &str, rule: &toml::Value) -> String {
+    let dir = rule
+        .get("dir")
+        .and_then(toml::Value::as_str)
+        .unwrap_or_default();
+    if let Err(e) = install::check_dir(dir) {
+        fail(LiebeError::InvalidTarget(format!("{target}: {e}")));
+    }
+    dir.to_string()
+}
+
+impl Target {
+    fn implicit() -> Self {
+        Self {
+            name: IMPLICIT_TARGET.into(),
+            kind: TargetKind::Executable,
+            description: None,
+            sources: Vec::new(),
+            deps: Vec::new(),
+            default: true,
+            build_hook: "build".into(),
+            run_hook: "run".into(),
+            timeout: None,
+            install: true,
+            install_files: Vec::new(),
+        }
+    }
+
+    fn from_toml(name: &str, value: &toml::Value) -> Self {
+        let string = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+        let strings = |key: &str| strings_of(value, key);
+        let kind = match string("kind").as_deref() {
+            Some("library") => TargetKind::Library,
+            Some("test") => TargetKind::Test,
+            Some("custom") => TargetKind::Custom,
+            _ => TargetKind::Executable,
+        };
+        Self {
+            name: name.into(),
+            kind,
+            description: string("description"),
+            sources: strings("sources"),
+            deps: strings("deps"),
+            default: value
+                .get("default")
+                .and_then(toml::Value::as_bool)
+                .unwrap_or(kind != TargetKind::Test),
+            build_hook: string("build").unwrap_or_else(|| "build".into()),
+            run_hook: string("run").unwrap_or_else(|| "run".into()),
+            timeout: value
+                .get("timeout")
+                .and_then(toml::Value::as_integer)
+                .and_then(|t| u64::try_from(t).ok()),
+            install: value
+                .get("install")
+                .and_then(toml::Value::as_bool)
+                .unwrap_or(true),
+            install_files: value
+                .get("install-files")
+                .and_then(toml::Value::as_array)
+                .into_iter()
+                .flatten()
+                .map(|rule| InstallFiles {
+                    files: strings_of(rule, "files"),
+                    dir: install_dir(name, rule),
+                    mode: rule
+                        .get("mode")
+                        .and_then(toml::Value::as_str)
+                        .map(String::from),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_runnable(&self) -> bool {
+        matches!(self.kind, TargetKind::Executable | TargetKind::Test)
+    }
+
+    // Source paths with glob patterns expanded relative to `base`, in a stable order
+    pub fn expand_sources(&self, base: &Path) -> Vec<String> {
+        self.expand(base, &self.sources)
+    }
+
+    pub fn expand(&self, base: &Path, patterns: &[String]) -> Vec<String> {
+        let mut out = Vec::new();
+        for pattern in patterns {
+            let Ok(paths) = glob::glob(&base.join(pattern).to_string_lossy()) else {
+                fail(LiebeError::InvalidTarget(format!(
+                    "{}: invalid pattern `{pattern}`",
+                    self.name
+                )));
+            };
+            let mut matched: Vec<String> = paths
+                .filter_map(Result::ok)
+                .map(|p| {
+                    p.strip_prefix(base)
+                        .unwrap_or(&p)
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect();
+            if matched.is_empty() && !pattern.contains(['*', '?', '[']) {
+                matched.push(pattern.clone());
+            }
+            matched.sort();
+            for path in matched {
+                if !out.contains(&path) {
+                    out.push(path);
+                }
+            }
+        }
+        out
+    }
+}
+
+// Targets declared in the `[targets.<name>]` sections of liebe.toml
+#[derive(Debug)]
+pub struct Targets {
+    declared: BTreeMap<String, Target>,
+}
+
+impl Targets {
+    pub fn from_config(config: &toml::Value) -> Self {
+        let mut declared: BTreeMap<String, Target> = config
+            .get("targets")
+            .and_then(toml::Value::as_table)
+            .map(|t| {
+                t.iter()
+                    .map(|(name, v)| (name.clone(), Target::from_toml(name, v)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if declared.is_empty() {
+            declared.insert(IMPLICIT_TARGET.into(), Target::implicit());
+        }
+        let targets = Self { declared };
+        // Dependencies on other workspace members are checked by the workspace
+        for target in targets.declared.values() {
+            for dep in target.deps.iter().filter(|d| !d.starts_with("//")) {
+                let dep = dep.trim_start_matches(':');
+                targets.check_exists(dep, &format!(" (dependency of `{}`)", target.name));
+            }
+        }
+        targets
+    }
+
+    pub fn get(&self, name: &str) -> &Target {
+        self.check_exists(name, "");
+        &self.declared[name]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Target> {
+        self.declared.values()
+    }
+
+    fn check_exists(&self, name: &str, context: &str) {
+        if self.declared.contains_key(name) {
+            return;
+        }
+        let hint = did_you_mean(name, self.declared.keys().map(String::as_str))
+            .map(|s| format!(", did you mean `{s}`?"))
+            .unwrap_or_default();
+        fail(LiebeError::UnknownTarget(format!(
+            "`{name}`{context}{hint}"
+        )));
+    }
+
+    // Targets built when none are named on the command line
+    pub fn defaults(&self) -> Vec<String> {
+        self.declared
+            .values()
+            .filter(|t| t.default)
+            .map(|t| t.name.clone())
+            .collect()
+    }
+
+    // Target `liebe run` uses when none is named: the only runnable default target
+    pub fn default_runnable(&self) -> &Target {
+        let runnable: Vec<&Target> = self
+            .declared
+            .values()
+            .filter(|t| t.default && t.is_runnable())
+            .collect();
+        match runnable.as_slice() {
+            [target] => target,
+            [] => fail(LiebeError::UnknownTarget(
+                "no runnable default target, name the target to run",
+            )),
+            many => fail(LiebeError::UnknownTarget(format!(
+                "several targets can be run, pick one of {}",
+                many.iter()
+                    .map(|t| t.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+}
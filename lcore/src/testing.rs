@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::build::{self, BuildRequest};
+use crate::error::{LiebeError, fail};
+use crate::runner::{Runner, Task, TaskSpec};
+use crate::utils::xml_escape;
+use crate::workspace::{TargetRef, Workspace};
+use serde_json::{Value, json};
+use std::fmt::Write;
+use std::time::Duration;
+
+/*
+TESTS
+`liebe test` runs the programs the run hooks of test targets return, each
+as a task of a runner: up to `jobs` of them at once, in the directory of
+their member, with their output captured. A test passes when it exits with
+0 before its timeout.
+*/
+
+// Selects the tests whose label contains a filter, or matches it as a glob pattern
+pub fn matches(label: &str, filters: &[String]) -> bool {
+    filters.is_empty()
+        || filters.iter().any(|f| {
+            label.contains(f.as_str()) || glob::Pattern::new(f).is_ok_and(|p| p.matches(label))
+        })
+}
+
+// `i/n` as given to `--shard`, with 1 <= i <= n
+pub fn parse_shard(shard: &str) -> (usize, usize) {
+    let parsed = shard
+        .split_once('/')
+        .and_then(|(i, n)| Some((i.parse::<usize>().ok()?, n.parse::<usize>().ok()?)));
+    match parsed {
+        Some((i, n)) if i >= 1 && i <= n => (i, n),
+        _ => fail(LiebeError::InvalidOption(format!(
+            "`{shard}` is not a shard, use i/n with 1 <= i <= n"
+        ))),
+    }
+}
+
+// The `i`th of `n` shards of the tests, the same one on every machine for the same tests
+pub fn shard<T>(tests: Vec<T>, (i, n): (usize, usize)) -> Vec<T> {
+    tests
+        .into_iter()
+        .enumerate()
+        .filter(|(id, _)| id % n == i - 1)
+        .map(|(_, test)| test)
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    pub label: String,
+    pub cmd: Vec<String>,
+    pub cwd: String,
+    pub timeout: Option<Duration>,
+}
+
+// Commands of the tests from their run hooks, `args` are appended to each of them
+pub fn cases(
+    ws: &Workspace,
+    tests: &[TargetRef],
+    request: &BuildRequest,
+    args: &[String],
+    timeout: Option<Duration>,
+) -> Vec<TestCase> {
+    tests
+        .iter()
+        .map(|r| {
+            let label = ws.label(r);
+            let Some(mut cmd) = build::run_command(ws, r, request) else {
+                fail(LiebeError::InvalidTarget(format!(
+                    "the run hook of `{label}` returned no command to test with"
+                )));
+            };
+            cmd.extend_from_slice(args);
+            let target = ws.project(r).targets.get(&r.name);
+            TestCase {
+                label,
+                cmd,
+                cwd: ws.project(r).dir().display().to_string(),
+                timeout: target.timeout.map(Duration::from_secs).or(timeout),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed(Option<i32>),
+    TimedOut(Duration),
+    // The test program could not be spawned
+    NotSpawned,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        *self == Self::Passed
+    }
+
+    // Why a test failed
+    pub fn message(&self) -> String {
+        match self {
+            Self::Passed => "passed".into(),
+            Self::Failed(Some(code)) => format!("exited with {code}"),
+            Self::Failed(None) => "killed by a signal".into(),
+            Self::TimedOut(timeout) => format!("timed out after {}s", timeout.as_secs()),
+            Self::NotSpawned => "could not be spawned".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub label: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+    pub output: Vec<String>,
+}
+
+// Reported by `--message-format=json`
+impl TestResult {
+    pub fn to_json(&self) -> Value {
+        let (outcome, exit_code) = match self.outcome {
+            Outcome::Passed => ("passed", Some(0)),
+            Outcome::Failed(code) => ("failed", code),
+            Outcome::NotSpawned => ("failed", None),
+            Outcome::TimedOut(_) => ("timed-out", None),
+        };
+        json!({
+            "name": self.label,
+            "outcome": outcome,
+            "exit_code": exit_code,
+            "duration_ms": self.duration.as_millis(),
+            "output": self.output,
+        })
+    }
+}
+
+// Without `jobs`, as many tests run at once as there are CPUs
+pub fn run(cases: &[TestCase], jobs: Option<usize>) -> Vec<TestResult> {
+    let mut runner = Runner::new();
+    if let Some(jobs) = jobs {
+        runner.max_proc = jobs.max(1);
+    }
+    for case in cases {
+        let spec = TaskSpec {
+            name: case.label.clone(),
+            target: case.label.clone(),
+            cmd: case.cmd.clone(),
+            cwd: Some(case.cwd.clone()),
+            ..Default::default()
+        };
+        let task = Task::from_spec(spec).non_fatal().with_timeout(case.timeout);
+        runner.add_task(task);
+    }
+    // The output of the tests is reported with their results
+    let runner = runner.on_event(|_| {}).run().wait();
+    runner
+        .tasks()
+        .iter()
+        .zip(cases)
+        .map(|(task, case)| {
+            let outcome = match (task.timed_out(), task.exit_code()) {
+                _ if !task.spawned() => Outcome::NotSpawned,
+                (true, _) => Outcome::TimedOut(case.timeout.unwrap_or_default()),
+                (false, Some(0)) => Outcome::Passed,
+                (false, code) => Outcome::Failed(code),
+            };
+            TestResult {
+                label: case.label.clone(),
+                outcome,
+                duration: task.duration(),
+                output: task.output().to_vec(),
+            }
+        })
+        .collect()
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+// JUnit XML as read by most CI services, all tests in a single suite
+pub fn junit(results: &[TestResult]) -> String {
+    let failures = results.iter().filter(|r| !r.outcome.passed()).count();
+    let total: Duration = results.iter().map(|r| r.duration).sum();
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        out,
+        r#"<testsuites name="liebe" tests="{}" failures="{failures}" time="{}">"#,
+        results.len(),
+        seconds(total)
+    );
+    let _ = writeln!(
+        out,
+        r#"  <testsuite name="liebe" tests="{}" failures="{failures}" errors="0" skipped="0" time="{}">"#,
+        results.len(),
+        seconds(total)
+    );
+    for result in results {
+        // `//member:target` is split into the class and the name of the test
+        let (class, name) = result
+            .label
+            .rsplit_once(':')
+            .unwrap_or(("", result.label.as_str()));
+        let _ = write!(
+            out,
+            r#"    <testcase classname="{}" name="{}" time="{}""#,
+            xml_escape(class.trim_start_matches('/')),
+            xml_escape(name),
+            seconds(result.duration)
+        );
+        if result.outcome.passed() && result.output.is_empty() {
+            let _ = writeln!(out, "/>");
+            continue;
+        }
+        let _ = writeln!(out, ">");
+        if !result.outcome.passed() {
+            let _ = writeln!(
+                out,
+                r#"      <failure message="{}"/>"#,
+                xml_escape(&result.outcome.message())
+            );
+        }
+        if !result.output.is_empty() {
+            let _ = writeln!(
+                out,
+                "      <system-out>{}</system-out>",
+                xml_escape(&result.output.join("\n"))
+            );
+        }
+        let _ = writeln!(out, "    </testcase>");
+    }
+    let _ = writeln!(out, "  </testsuite>");
+    let _ = writeln!(out, "</testsuites>");
+    out
+}
+
+// TAP version 13, the output of failed tests follows them as diagnostics
+pub fn tap(results: &[TestResult]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "TAP version 13");
+    let _ = writeln!(out, "1..{}", results.len());
+    for (id, result) in results.iter().enumerate() {
+        let status = if result.outcome.passed() {
+            "ok"
+        } else {
+            "not ok"
+        };
+        let _ = writeln!(out, "{status} {} - {}", id + 1, result.label);
+        if result.outcome.passed() {
+            continue;
+        }
+        let _ = writeln!(out, "  ---");
+        let _ = writeln!(out, "  message: {}", json!(result.outcome.message()));
+        let _ = writeln!(out, "  duration_ms: {}", result.duration.as_millis());
+        let _ = writeln!(out, "  ...");
+        for line in &result.output {
+            let _ = writeln!(out, "# {line}");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn unspawnable_tests_fail_without_stopping_the_others() {
+        let case = |label: &str, cmd: &str| TestCase {
+            label: label.into(),
+            cmd: vec![cmd.into()],
+            cwd: env::temp_dir().display().to_string(),
+            timeout: None,
+        };
+        let results = run(
+            &[case("missing", "./liebe-no-such-test"), case("ok", "true")],
+            Some(1),
+        );
+        assert_eq!(results[0].outcome, Outcome::NotSpawned);
+        assert!(results[0].output[0].starts_with("Cannot spawn `./liebe-no-such-test`"));
+        assert_eq!(results[1].outcome, Outcome::Passed);
+    }
+}
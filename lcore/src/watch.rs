@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::error::{ExitOnError, LiebeError, fail};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// Time without changes after which a burst of changes is over, eg. a save of several files
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+const POLL: Duration = Duration::from_millis(50);
+
+/*
+WATCHER
+Watches files through inotify for `--watch`. Directories are watched rather
+than the files themselves: editors often save by writing a new file and
+renaming it over the old one, which a watch on the old file would not see.
+Events for names that are not watched in a directory are ignored.
+*/
+pub struct Watcher {
+    inotify: Inotify,
+    // Watched names in every watched directory
+    files: BTreeMap<PathBuf, BTreeSet<OsString>>,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        Self {
+            inotify: Inotify::init().log(LiebeError::CannotWatchFiles("inotify is not available")),
+            files: BTreeMap::new(),
+            dirs: HashMap::new(),
+        }
+    }
+
+    // Files in directories that do not exist cannot change without another watched file changing first
+    pub fn watch<P: AsRef<Path>>(&mut self, files: impl IntoIterator<Item = P>) {
+        let mask = WatchMask::CLOSE_WRITE
+            | WatchMask::MODIFY
+            | WatchMask::ATTRIB
+            | WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO;
+        for file in files {
+            let file = file.as_ref();
+            let (Some(dir), Some(name)) = (file.parent(), file.file_name()) else {
+                continue;
+            };
+            if !self.files.contains_key(dir) {
+                let Ok(wd) = self.inotify.watches().add(dir, mask) else {
+                    continue;
+                };
+                self.dirs.insert(wd, dir.to_path_buf());
+            }
+            self.files
+                .entry(dir.to_path_buf())
+                .or_default()
+                .insert(name.to_os_string());
+        }
+    }
+
+    /*
+    Watched files that changed since the last call, without waiting. When the
+    kernel dropped events, every watched file is returned.
+    */
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut buffer = [0; 4096];
+        let mut changed: Vec<PathBuf> = Vec::new();
+        loop {
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return changed,
+                Err(_) => fail(LiebeError::CannotWatchFiles("reading events failed")),
+            };
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    changed = self.all();
+                    continue;
+                }
+                let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+                    continue;
+                };
+                let path = dir.join(name);
+                let watched = self.files.get(dir).is_some_and(|f| f.contains(name));
+                if watched && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+    }
+
+    // Waits for watched files to change, until no more changes came for `debounce`
+    pub fn changes(&mut self, debounce: Duration, mut idle: impl FnMut()) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = Vec::new();
+        let mut last = Instant::now();
+        loop {
+            let now = self.poll();
+            if !now.is_empty() {
+                last = Instant::now();
+            }
+            for path in now {
+                if !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+            if !changed.is_empty() && last.elapsed() >= debounce {
+                return changed;
+            }
+            idle();
+            sleep(POLL);
+        }
+    }
+
+    fn all(&self) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .flat_map(|(dir, names)| names.iter().map(|name| dir.join(name)))
+            .collect()
+    }
+}
@@ -0,0 +1,439 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 coppamocha
+use crate::empty_err;
+use crate::error::{ExitOnError, LiebeError, fail};
+use crate::luaapi::LuaApi;
+use crate::schema::{Field, Schema};
+use crate::target::{TargetKind, Targets};
+use crate::utils::did_you_mean;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE: &str = "liebe.toml";
+pub const DEFAULT_BUILD_DIR: &str = "build";
+pub const CACHE_DIR: &str = ".liebe";
+
+pub fn workspace_schema() -> Schema {
+    Schema::Table(vec![
+        Field::required("members", Schema::strings()),
+        Field::new("build-dir", Schema::String),
+    ])
+}
+
+fn read_config(path: &Path) -> toml::Value {
+    let contents =
+        fs::read_to_string(path).log(LiebeError::CannotReadFile(path.display().to_string()));
+    toml::from_str(&contents).log(empty_err!(InvalidConf))
+}
+
+// Closest directory at or above `start` containing a liebe.toml
+fn find_upwards(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(CONFIG_FILE).is_file())
+        .map(Path::to_path_buf)
+}
+
+pub struct Project {
+    // Directory of the member relative to the workspace root, empty for the root project
+    pub path: String,
+    // liebe.toml of the member, or the file given by `--config`
+    pub config: PathBuf,
+    pub lua: LuaApi,
+    pub targets: Targets,
+}
+
+impl Project {
+    fn load(path: String, config: &Path) -> Self {
+        let mut lua = LuaApi::new(config);
+        lua.invoke();
+        let targets = Targets::from_config(lua.config());
+        Self {
+            path,
+            config: config.to_path_buf(),
+            lua,
+            targets,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.lua.dir()
+    }
+
+    pub fn label(&self, target: &str) -> String {
+        if self.path.is_empty() {
+            target.to_string()
+        } else {
+            format!("//{}:{target}", self.path)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetRef {
+    pub project: usize,
+    pub name: String,
+}
+
+/*
+WORKSPACE
+A root liebe.toml with a `[workspace]` section lists member directories, each
+with its own liebe.toml and lang-script. Targets of other members are referred
+to as `//<member path>:<target>`, plain names refer to the current member.
+A project outside of any workspace is a workspace with a single root member.
+*/
+pub struct Workspace {
+    pub root: PathBuf,
+    root_config: toml::Value,
+    pub projects: Vec<Project>,
+    // Project liebe was invoked from, `None` at the root of a pure workspace
+    current: Option<usize>,
+}
+
+impl Workspace {
+    // Loads the workspace of the closest liebe.toml at or above `start`
+    pub fn discover(start: &Path) -> Self {
+        Self::open(&Self::config_for(start))
+    }
+
+    // The closest liebe.toml at or above `start`
+    pub fn config_for(start: &Path) -> PathBuf {
+        let nearest = find_upwards(start).log(LiebeError::CannotOpenFile(format!(
+            "no {CONFIG_FILE} in {} or any parent directory",
+            start.display()
+        )));
+        nearest.join(CONFIG_FILE)
+    }
+
+    /*
+    Loads the workspace `config` belongs to. The file may have any name, it
+    stands in for the liebe.toml of its directory. If it has no `[workspace]`
+    section, the closest liebe.toml above listing its directory as a member
+    is the workspace root.
+    */
+    pub fn open(config: &Path) -> Self {
+        let (config, root, root_config) = Self::locate(config);
+        let nearest = config.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let mut paths = Self::member_paths(&root, &root_config);
+        if root_config.get("lang-script").is_some() {
+            paths.insert(0, String::new());
+        }
+        let current_path = relative(&root, &nearest);
+        let projects: Vec<Project> = paths
+            .into_iter()
+            .map(|path| {
+                let file = if path == current_path {
+                    config.clone()
+                } else {
+                    root.join(&path).join(CONFIG_FILE)
+                };
+                Project::load(path, &file)
+            })
+            .collect();
+        let current = projects.iter().position(|p| p.path == current_path);
+        Self {
+            root,
+            root_config,
+            projects,
+            current,
+        }
+    }
+
+    // The canonical `config`, the root of its workspace and the configuration there
+    fn locate(config: &Path) -> (PathBuf, PathBuf, toml::Value) {
+        let config =
+            fs::canonicalize(config).log(LiebeError::CannotOpenFile(config.display().to_string()));
+        let nearest = config.parent().map(Path::to_path_buf).unwrap_or_default();
+        let nearest_config = read_config(&config);
+
+        let (root, root_config) = if nearest_config.get("workspace").is_some() {
+            (nearest.clone(), nearest_config)
+        } else {
+            nearest
+                .parent()
+                .and_then(find_upwards)
+                .map(|dir| (dir.clone(), read_config(&dir.join(CONFIG_FILE))))
+                .filter(|(dir, config)| {
+                    Self::member_paths(dir, config).contains(&relative(dir, &nearest))
+                })
+                .unwrap_or((nearest.clone(), nearest_config))
+        };
+        (config, root, root_config)
+    }
+
+    // Root of the workspace `config` belongs to, without loading any member
+    pub fn root_of(config: &Path) -> PathBuf {
+        Self::locate(config).1
+    }
+
+    // Makes the member whose liebe.toml is in `dir` the current one, none if no member is there
+    pub fn set_current(&mut self, dir: &Path) {
+        self.current = self.projects.iter().position(|p| p.dir() == dir);
+    }
+
+    // Member directories listed by `workspace.members`, globs expanded
+    fn member_paths(root: &Path, config: &toml::Value) -> Vec<String> {
+        let Some(members) = config
+            .get("workspace")
+            .and_then(|w| w.get("members"))
+            .and_then(toml::Value::as_array)
+        else {
+            return Vec::new();
+        };
+        let mut paths = Vec::new();
+        for pattern in members.iter().filter_map(toml::Value::as_str) {
+            let full = root.join(pattern).display().to_string();
+            let matches = glob::glob(&full).log(LiebeError::InvalidWorkspace(format!(
+                "invalid member pattern `{pattern}`"
+            )));
+            for dir in matches.filter_map(Result::ok) {
+                let path = relative(root, &dir);
+                if dir.join(CONFIG_FILE).is_file() && !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+        paths
+    }
+
+    fn current_id(&self) -> usize {
+        self.current.log(LiebeError::InvalidWorkspace(
+            "not inside a workspace member, use //<member>:<target>",
+        ))
+    }
+
+    pub fn current(&self) -> &Project {
+        &self.projects[self.current_id()]
+    }
+
+    pub fn project(&self, r: &TargetRef) -> &Project {
+        &self.projects[r.project]
+    }
+
+    pub fn label(&self, r: &TargetRef) -> String {
+        self.project(r).label(&r.name)
+    }
+
+    fn project_by_path(&self, path: &str) -> usize {
+        let path = path.trim_end_matches('/');
+        if let Some(id) = self.projects.iter().position(|p| p.path == path) {
+            return id;
+        }
+        let hint = did_you_mean(path, self.projects.iter().map(|p| p.path.as_str()))
+            .map(|s| format!(", did you mean `//{s}`?"))
+            .unwrap_or_default();
+        fail(LiebeError::UnknownTarget(format!(
+            "no workspace member at `//{path}`{hint}"
+        )));
+    }
+
+    // Resolves `//member:target`, `:target` or `target` as seen from project `from`
+    pub fn resolve(&self, from: Option<usize>, reference: &str) -> TargetRef {
+        let (project, name) = match reference.strip_prefix("//") {
+            Some(rest) => {
+                let (path, name) = rest.split_once(':').log(LiebeError::UnknownTarget(format!(
+                    "`{reference}` must be of the form //<member>:<target>"
+                )));
+                (self.project_by_path(path), name)
+            }
+            None => (
+                from.unwrap_or_else(|| self.current_id()),
+                reference.trim_start_matches(':'),
+            ),
+        };
+        self.projects[project].targets.get(name);
+        TargetRef {
+            project,
+            name: name.to_string(),
+        }
+    }
+
+    // Like `resolve`, but a bare `//member` stands for all default targets of that member
+    pub fn resolve_many(&self, reference: &str) -> Vec<TargetRef> {
+        match reference.strip_prefix("//") {
+            Some(path) if !path.contains(':') => self.project_defaults(self.project_by_path(path)),
+            _ => vec![self.resolve(self.current, reference)],
+        }
+    }
+
+    fn project_defaults(&self, project: usize) -> Vec<TargetRef> {
+        self.projects[project]
+            .targets
+            .defaults()
+            .into_iter()
+            .map(|name| TargetRef { project, name })
+            .collect()
+    }
+
+    // Default targets of the current member, or of every member at the workspace root
+    pub fn defaults(&self) -> Vec<TargetRef> {
+        match self.current {
+            Some(id) => self.project_defaults(id),
+            None => (0..self.projects.len())
+                .flat_map(|id| self.project_defaults(id))
+                .collect(),
+        }
+    }
+
+    // Every target of every member
+    pub fn all_targets(&self) -> Vec<TargetRef> {
+        (0..self.projects.len())
+            .flat_map(|project| {
+                self.projects[project]
+                    .targets
+                    .iter()
+                    .map(move |t| TargetRef {
+                        project,
+                        name: t.name.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    // Test targets of the current member, or of every member at the workspace root
+    pub fn tests(&self) -> Vec<TargetRef> {
+        let projects = match self.current {
+            Some(id) => id..id + 1,
+            None => 0..self.projects.len(),
+        };
+        projects
+            .flat_map(|project| {
+                self.projects[project]
+                    .targets
+                    .iter()
+                    .filter(|t| t.kind == TargetKind::Test)
+                    .map(move |t| TargetRef {
+                        project,
+                        name: t.name.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    // Target `liebe run` uses when none is named, taken from the current member
+    pub fn default_runnable(&self) -> TargetRef {
+        let project = self.current_id();
+        TargetRef {
+            project,
+            name: self.projects[project]
+                .targets
+                .default_runnable()
+                .name
+                .clone(),
+        }
+    }
+
+    // Target `liebe run` runs: the one named, or the default one of the current member
+    pub fn runnable(&self, name: Option<&str>) -> TargetRef {
+        let r = match name {
+            Some(name) => self.resolve(None, name),
+            None => self.default_runnable(),
+        };
+        let target = self.project(&r).targets.get(&r.name);
+        if !target.is_runnable() {
+            fail(LiebeError::InvalidTarget(format!(
+                "`{}` is a {} and cannot be run",
+                self.label(&r),
+                target.kind.as_str()
+            )));
+        }
+        r
+    }
+
+    // The requested targets and everything they depend on, dependencies first
+    pub fn build_order(&self, requested: &[TargetRef]) -> Vec<TargetRef> {
+        let mut order = Vec::new();
+        let mut stack = Vec::new();
+        for r in requested {
+            self.visit(r, &mut stack, &mut order);
+        }
+        order
+    }
+
+    fn visit(&self, r: &TargetRef, stack: &mut Vec<TargetRef>, order: &mut Vec<TargetRef>) {
+        if order.contains(r) {
+            return;
+        }
+        if stack.contains(r) {
+            stack.push(r.clone());
+            fail(LiebeError::InvalidTarget(format!(
+                "dependency cycle: {}",
+                stack
+                    .iter()
+                    .map(|r| self.label(r))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            )));
+        }
+        stack.push(r.clone());
+        for dep in self.dep_refs(r) {
+            self.visit(&dep, stack, order);
+        }
+        stack.pop();
+        order.push(r.clone());
+    }
+
+    pub fn dep_refs(&self, r: &TargetRef) -> Vec<TargetRef> {
+        self.project(r)
+            .targets
+            .get(&r.name)
+            .deps
+            .iter()
+            .map(|dep| self.resolve(Some(r.project), dep))
+            .collect()
+    }
+
+    // Build directories of all members share one root: <build-dir>/<profile>/<member path>
+    pub fn build_dir(&self, project: usize, profile: &str) -> PathBuf {
+        let build_root = ["workspace", "project"]
+            .iter()
+            .find_map(|section| {
+                self.root_config
+                    .get(section)
+                    .and_then(|s| s.get("build-dir"))
+                    .and_then(toml::Value::as_str)
+            })
+            .unwrap_or(DEFAULT_BUILD_DIR);
+        let dir = self.root.join(build_root).join(profile);
+        match self.projects[project].path.as_str() {
+            "" => dir,
+            path => dir.join(path),
+        }
+    }
+
+    // Files the workspace is loaded from, the root liebe.toml first when there is one
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        let root = self.root.join(CONFIG_FILE);
+        let mut files: Vec<PathBuf> = Some(root).filter(|f| f.is_file()).into_iter().collect();
+        for project in &self.projects {
+            if !files.contains(&project.config) {
+                files.push(project.config.clone());
+            }
+        }
+        files
+    }
+
+    // Lang-scripts and lua modules the members loaded from disk
+    pub fn script_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        for script in self.projects.iter().flat_map(|p| p.lua.scripts()) {
+            if !files.contains(&script) {
+                files.push(script);
+            }
+        }
+        files
+    }
+
+    // Cache shared by all members
+    pub fn cache_dir(&self) -> PathBuf {
+        self.root.join(CACHE_DIR)
+    }
+}
+
+fn relative(root: &Path, dir: &Path) -> String {
+    dir.strip_prefix(root)
+        .map(|p| p.to_string_lossy().trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| dir.display().to_string())
+}
@@ -1,50 +1,1049 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 coppamocha
 use clap::{Arg, ArgAction, ArgMatches, Command};
-use lcore::{error::set_verbose, luaapi::LuaApi};
+use lcore::{
+    build::{self, BuildRequest, DEFAULT_PROFILE},
+    clean::{self, Selection},
+    compdb::{self, COMPDB_FILE},
+    depfile::{self, DepStyle},
+    dot,
+    error::{ExitOnError, LiebeError, fail, set_verbose},
+    exporter::Regen,
+    graph::TaskGraph,
+    install::{self, Manifest},
+    logs::{LastRun, RunLog},
+    make::{self, MAKE_FILE},
+    ninja::{self, NINJA_FILE},
+    profile::Profiles,
+    report,
+    runner::{Runner, Task, TaskStatus},
+    say, scaffold,
+    state::StateDb,
+    target::Target,
+    testing::{self, Outcome, TestResult},
+    utils::shell_quote,
+    workspace::{CACHE_DIR, TargetRef, Workspace},
+};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use lcore::{
+    daemon,
+    watch::{DEBOUNCE, Watcher},
+};
 
 pub const VERSION: &str = "0.1";
 
 pub struct Cli {
     matches: ArgMatches,
     pub unmatched_args: Vec<String>,
+    // Directory liebe was started from, before `-C`
+    start_dir: PathBuf,
 }
 
 impl Cli {
-    pub fn apply_callbacks(self, lua: &LuaApi) {
-        match self.matches.subcommand() {
-            Some(("build", subc)) => {
-                let target = subc
-                    .get_one::<String>("target")
-                    .expect("Expected a target to build");
-                Self::on_build(target, lua);
-            }
-            Some(("run", subc)) => {
-                let target = subc
-                    .get_one::<String>("target")
-                    .expect("Expected a target to build and run");
-                Self::on_run(target, lua);
+    // `-C <dir>` behaves as if liebe was started in <dir>
+    pub fn enter_directory(&self) {
+        if let Some(dir) = self.matches.get_one::<PathBuf>("directory") {
+            env::set_current_dir(dir).log(LiebeError::CannotOpenFile(dir.display().to_string()));
+        }
+    }
+
+    // The file given by `--config`, or the closest liebe.toml upwards
+    fn config(&self) -> PathBuf {
+        match self.matches.get_one::<PathBuf>("config") {
+            Some(config) => config.clone(),
+            None => {
+                let cwd = env::current_dir().log(LiebeError::CannotOpenFile("current directory"));
+                Workspace::config_for(&cwd)
+            }
+        }
+    }
+
+    // Also used to reload the workspace when its configuration changed
+    pub fn workspace(&self) -> Workspace {
+        Workspace::open(&self.config())
+    }
+
+    /*
+    `liebe depfile <file>` makes the paths of a depfile absolute, from the
+    directory it runs in. The build files of `liebe gen` run it after the
+    tasks writing depfiles, see exporter.rs.
+    */
+    pub fn apply_depfile(&self) -> bool {
+        let Some(("depfile", subc)) = self.matches.subcommand() else {
+            return false;
+        };
+        let file = subc.get_one::<PathBuf>("file").expect("Expected a depfile");
+        let name = file.display().to_string();
+        let contents = fs::read_to_string(file).log(LiebeError::CannotReadFile(name.clone()));
+        let cwd = env::current_dir().log(LiebeError::CannotOpenFile("current directory"));
+        fs::write(file, depfile::absolute_make(&contents, &cwd))
+            .log(LiebeError::CannotWriteFile(name));
+        true
+    }
+
+    // `liebe init` runs before there is a workspace to load
+    pub fn apply_init(&self) -> bool {
+        let Some(("init", subc)) = self.matches.subcommand() else {
+            return false;
+        };
+        let cwd = env::current_dir().log(LiebeError::CannotOpenFile("current directory"));
+        let dir: PathBuf = cwd
+            .join(subc.get_one::<PathBuf>("dir").expect("dir has a default"))
+            .components()
+            .collect();
+        let name = match subc.get_one::<String>("name") {
+            Some(given) => {
+                let name = scaffold::sanitize_name(given);
+                if name != *given {
+                    println!("Naming the project `{name}`, `{given}` is not a valid target name");
+                }
+                name
             }
+            None => scaffold::project_name(&dir),
+        };
+        let lang = subc.get_one::<String>("lang").expect("lang has a default");
+        for path in scaffold::init(&dir, lang, &name) {
+            println!("Wrote {}", path.display());
+        }
+        println!("Build it with `liebe build`, run it with `liebe run`");
+        true
+    }
+
+    pub fn apply_callbacks(&self, ws: &Workspace) {
+        match self.matches.subcommand() {
+            Some(("build", subc)) => self.on_build(subc, ws),
+            Some(("run", subc)) => self.on_run(subc, ws),
+            Some(("compdb", subc)) => Self::on_compdb(subc, ws, &self.unmatched_args),
+            Some(("test", subc)) => self.on_test(subc, ws),
+            Some(("clean", subc)) => Self::on_clean(subc, ws),
+            Some(("install", subc)) => self.on_install(subc, ws),
+            Some(("uninstall", _)) => Self::on_uninstall(ws),
+            Some(("log", subc)) => Self::on_log(subc, ws),
+            Some(("targets", _)) => Self::on_targets(ws),
+            Some(("tasks", subc)) => self.on_tasks(subc, ws),
+            Some(("graph", subc)) => self.on_graph(subc, ws),
+            Some(("profiles", _)) => Self::on_profiles(ws),
+            Some(("gen", subc)) => self.on_gen(subc, ws),
             _ => {}
         }
         set_verbose(self.matches.get_flag("verbose"));
     }
 
-    fn on_build(target: &String, lua: &LuaApi) {
-        let context = lua.create_table();
-        context
-            .set("target", target.to_string())
-            .expect("Couldnt set value to build_conf");
-        lua.add_context("build_conf", context);
-        lua.call::<()>("build");
+    fn request(subc: &ArgMatches, args: &[String]) -> BuildRequest {
+        let profile = subc
+            .get_one::<String>("profile")
+            .expect("Expected a profile to build with");
+        let defines = subc
+            .get_many::<String>("define")
+            .unwrap_or_default()
+            .cloned()
+            .collect::<Vec<String>>();
+        BuildRequest {
+            profile: profile.clone(),
+            defines,
+            args: args.to_vec(),
+            read_only: false,
+        }
+    }
+
+    fn execute(graph: TaskGraph, ws: &Workspace, subc: &ArgMatches) {
+        if !Self::build(graph.into_runner(), &ws.cache_dir(), subc) {
+            exit(1);
+        }
+    }
+
+    // With `--dry-run` the tasks that would run are only listed, `--explain` tells why they run
+    fn build(runner: Runner, cache_dir: &Path, subc: &ArgMatches) -> bool {
+        let mut runner = runner.with_state(StateDb::load(cache_dir));
+        let explain = Self::flag(subc, "explain");
+        if Self::flag(subc, "dry-run") {
+            let plan = runner.plan();
+            let dirty = runner
+                .tasks()
+                .iter()
+                .zip(&plan)
+                .enumerate()
+                .filter_map(|(id, (task, reason))| reason.as_ref().map(|r| (id, task, r)));
+            let mut count = 0;
+            for (id, task, reason) in dirty {
+                report::planned(id, task, reason, explain);
+                count += 1;
+            }
+            say!("{count} of {} tasks would run", plan.len());
+            return true;
+        }
+        let mut print = report::printer(explain);
+        let mut log = RunLog::new(cache_dir);
+        runner = runner.on_event(move |event| {
+            print(event);
+            log.record(event);
+        });
+        let runner = runner.run().wait();
+        report::build_finished(&runner);
+        runner.get_status() == TaskStatus::completed()
+    }
+
+    // Flags not every subcommand has
+    fn flag(subc: &ArgMatches, id: &str) -> bool {
+        subc.try_get_one::<bool>(id)
+            .ok()
+            .flatten()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn requested(subc: &ArgMatches, ws: &Workspace) -> Vec<TargetRef> {
+        subc.get_many::<String>("targets")
+            .unwrap_or_default()
+            .flat_map(|t| ws.resolve_many(t))
+            .collect()
+    }
+
+    fn on_build(&self, subc: &ArgMatches, ws: &Workspace) {
+        let request = Self::request(subc, &self.unmatched_args);
+        if subc.get_flag("watch") {
+            self.watch(subc, ws, &request, |ws| Self::requested(subc, ws), false);
+        }
+        let requested = Self::requested(subc, ws);
+        Self::execute(build::generate(ws, &requested, &request), ws, subc);
+    }
+
+    /*
+    `--watch` builds, waits for a source, a liebe.toml or a lua script to
+    change, and builds again until interrupted. The workspace is loaded again
+    when its configuration or scripts changed. With `run`, the program is
+    restarted after every successful build. A failed build keeps watching,
+    errors in the configuration end it.
+    */
+    #[cfg(target_os = "linux")]
+    fn watch(
+        &self,
+        subc: &ArgMatches,
+        ws: &Workspace,
+        request: &BuildRequest,
+        targets: impl Fn(&Workspace) -> Vec<TargetRef>,
+        run: bool,
+    ) -> ! {
+        let mut reloaded: Option<Workspace> = None;
+        let mut program: Option<Task> = None;
+        loop {
+            let ws = reloaded.as_ref().unwrap_or(ws);
+            let requested = targets(ws);
+            let graph = build::generate(ws, &requested, request);
+            let config: Vec<PathBuf> = ws
+                .config_files()
+                .into_iter()
+                .chain(ws.script_files())
+                .collect();
+
+            // Watching starts before the build to see the changes made while it runs
+            let mut watcher = Watcher::new();
+            watcher.watch(&config);
+            watcher.watch(graph.source_files(&StateDb::load(&ws.cache_dir())));
+            let tasks = graph.clone();
+            let built = Self::build(graph.into_runner(), &ws.cache_dir(), subc);
+            // Headers included for the first time
+            watcher.watch(tasks.source_files(&StateDb::load(&ws.cache_dir())));
+
+            if run && built {
+                if let Some(task) = program.as_mut() {
+                    task.kill();
+                }
+                program = build::run_command(ws, &requested[0], request).map(|mut cmd| {
+                    cmd.extend_from_slice(&self.unmatched_args);
+                    let mut task = Task::new(cmd).interactive().non_fatal();
+                    task.run();
+                    report::program_started(&task);
+                    if task.get_status() != TaskStatus::running() {
+                        report::program_finished(&task);
+                    }
+                    task
+                });
+            }
+            say!("Watching for changes...");
+            let changed = watcher.changes(DEBOUNCE, || {
+                let running = program
+                    .as_mut()
+                    .filter(|task| task.status() == TaskStatus::running());
+                if let Some(task) = running
+                    && task.get_status() != TaskStatus::running()
+                {
+                    report::program_finished(task);
+                }
+            });
+            for path in &changed {
+                say!("Changed: {}", path.display());
+            }
+            if changed.iter().any(|path| config.contains(path)) {
+                reloaded = Some(self.workspace());
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn watch(
+        &self,
+        _: &ArgMatches,
+        _: &Workspace,
+        _: &BuildRequest,
+        _: impl Fn(&Workspace) -> Vec<TargetRef>,
+        _: bool,
+    ) -> ! {
+        fail(LiebeError::CannotWatchFiles(
+            "inotify is only available on Linux",
+        ))
+    }
+    /*
+    `build` and `run` ask the daemon of the workspace for the tasks to run
+    when one is running. It falls back to loading the workspace when the
+    daemon does not answer, eg. because it stopped on an error.
+    */
+    #[cfg(target_os = "linux")]
+    pub fn apply_daemon(&self) -> bool {
+        match self.matches.subcommand() {
+            Some(("daemon", subc)) => {
+                self.on_daemon(subc);
+                true
+            }
+            Some((name @ ("build" | "run"), subc))
+                if !subc.get_flag("watch") && !self.matches.get_flag("no-daemon") =>
+            {
+                self.via_daemon(name == "run", subc)
+            }
+            _ => false,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply_daemon(&self) -> bool {
+        if let Some(("daemon", _)) = self.matches.subcommand() {
+            fail(LiebeError::DaemonError(
+                "the daemon is only available on Linux",
+            ));
+        }
+        false
+    }
+
+    #[cfg(target_os = "linux")]
+    fn canonical_config(&self) -> PathBuf {
+        let config = self.config();
+        config
+            .canonicalize()
+            .log(LiebeError::CannotOpenFile(config.display().to_string()))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn via_daemon(&self, run: bool, subc: &ArgMatches) -> bool {
+        let config = self.canonical_config();
+        let cache_dir = Workspace::root_of(&config).join(CACHE_DIR);
+        if !daemon::socket_path(&cache_dir).exists() {
+            return false;
+        }
+        let target = run.then(|| {
+            subc.get_one::<String>("target")
+                .cloned()
+                .unwrap_or_default()
+        });
+        let request = daemon::Request {
+            config,
+            build: Self::request(subc, &self.unmatched_args),
+            targets: subc
+                .try_get_many::<String>("targets")
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+            run: target,
+        };
+        let reply = match daemon::request(&cache_dir, &request) {
+            Some(Ok(reply)) => reply,
+            Some(Err(error)) => {
+                eprintln!("{error}");
+                exit(1);
+            }
+            None => {
+                say!("The daemon did not answer, building without it");
+                return false;
+            }
+        };
+        let runner = reply.graph.into_runner().with_plan(reply.plan);
+        if !Self::build(runner, &cache_dir, subc) {
+            exit(1);
+        }
+        if let Some(program) = reply.program {
+            self.run_program(program, subc);
+        }
+        true
+    }
+
+    #[cfg(target_os = "linux")]
+    fn on_daemon(&self, subc: &ArgMatches) {
+        let config = self.canonical_config();
+        let root = Workspace::root_of(&config);
+        let cache_dir = root.join(CACHE_DIR);
+        match subc.subcommand_name() {
+            Some("start") => Self::start_daemon(&config, &root, &cache_dir),
+            Some("stop") if daemon::stop(&cache_dir) => println!("Daemon stopped"),
+            Some("stop") => println!("No daemon running"),
+            Some("status") => match daemon::status(&cache_dir) {
+                Some(status) => println!(
+                    "Daemon running (pid {}) for {}, {} files tracked",
+                    status["pid"],
+                    status["root"].as_str().unwrap_or_default(),
+                    status["files"]
+                ),
+                None => println!("No daemon running"),
+            },
+            Some("serve") => daemon::serve(&config),
+            _ => {}
+        }
+    }
+
+    /*
+    The daemon runs `liebe daemon serve` detached from the terminal, its output
+    goes to a log. It outlives liebe and is reaped by init, so it is not waited for.
+    */
+    #[cfg(target_os = "linux")]
+    #[allow(clippy::zombie_processes)]
+    fn start_daemon(config: &Path, root: &Path, cache_dir: &Path) {
+        use std::os::unix::process::CommandExt;
+        use std::process::{Command as Process, Stdio};
+
+        if let Some(status) = daemon::status(cache_dir) {
+            println!("Daemon already running (pid {})", status["pid"]);
+            return;
+        }
+        fs::create_dir_all(cache_dir)
+            .log(LiebeError::CannotWriteFile(cache_dir.display().to_string()));
+        let log_path = cache_dir.join(daemon::LOG_FILE);
+        let log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .log(LiebeError::CannotWriteFile(log_path.display().to_string()));
+        let exe = env::current_exe().log(LiebeError::CannotOpenFile("liebe executable"));
+        let cloned = log
+            .try_clone()
+            .log(LiebeError::CannotWriteFile(log_path.display().to_string()));
+        let mut child = Process::new(exe)
+            .args(["daemon", "serve", "--config"])
+            .arg(config)
+            .current_dir(root)
+            .stdin(Stdio::null())
+            .stdout(log)
+            .stderr(cloned)
+            .process_group(0)
+            .spawn()
+            .log(LiebeError::CantSpawnChildProc("liebe daemon serve"));
+
+        // The daemon answers once the workspace is loaded, it exits on errors in the configuration
+        for _ in 0..100 {
+            if let Some(status) = daemon::status(cache_dir) {
+                println!("Daemon started (pid {})", status["pid"]);
+                return;
+            }
+            if let Ok(Some(_)) = child.try_wait() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        fail(LiebeError::DaemonError(format!(
+            "the daemon did not start, see {}",
+            log_path.display()
+        )));
+    }
+
+    // Writes the compilation database of the requested targets without building them
+    fn on_compdb(subc: &ArgMatches, ws: &Workspace, args: &[String]) {
+        // Only the graph is needed, the options of the build directories are left alone
+        let request = BuildRequest {
+            read_only: true,
+            ..Self::request(subc, args)
+        };
+        let graph = build::generate(ws, &Self::requested(subc, ws), &request);
+        let path = subc
+            .get_one::<PathBuf>("output")
+            .cloned()
+            .unwrap_or_else(|| ws.root.join(COMPDB_FILE));
+        fs::write(&path, compdb::generate(&graph))
+            .log(LiebeError::CannotWriteFile(path.display().to_string()));
+        println!("Wrote {}", path.display());
+    }
+    fn on_gen(&self, subc: &ArgMatches, ws: &Workspace) {
+        let Some((backend, subc)) = subc.subcommand() else {
+            return;
+        };
+        let request = Self::request(subc, &self.unmatched_args);
+        let requested = Self::requested(subc, ws);
+        let graph = build::generate(ws, &requested, &request);
+        let file = match backend {
+            "ninja" => NINJA_FILE,
+            "make" => MAKE_FILE,
+            _ => return,
+        };
+        let path = subc
+            .get_one::<PathBuf>("output")
+            .cloned()
+            .unwrap_or_else(|| ws.root.join(file));
+        let path = env::current_dir()
+            .map(|cwd| cwd.join(&path))
+            .unwrap_or(path);
+
+        // Labels of the requested targets that have tasks, built by default
+        let labels = if requested.is_empty() {
+            ws.defaults()
+        } else {
+            requested
+        };
+        let targets = graph.targets();
+        let defaults: Vec<String> = labels
+            .iter()
+            .map(|r| ws.label(r))
+            .filter(|label| targets.iter().any(|(t, _)| t == label))
+            .collect();
+        let regen = Regen {
+            cwd: self.start_dir.display().to_string(),
+            argv: env::args().collect(),
+            inputs: ws
+                .config_files()
+                .iter()
+                .chain(&ws.script_files())
+                .map(|f| f.display().to_string())
+                .collect(),
+        };
+        let output = path.display().to_string();
+        let contents = match backend {
+            "ninja" => ninja::generate(&graph, &defaults, &regen, &output, &ws.cache_dir()),
+            _ => make::generate(&graph, &defaults, &regen, &output),
+        };
+        fs::write(&path, contents).log(LiebeError::CannotWriteFile(path.display().to_string()));
+        println!("Wrote {}", path.display());
+    }
+    fn run_target(subc: &ArgMatches, ws: &Workspace) -> TargetRef {
+        ws.runnable(subc.get_one::<String>("target").map(String::as_str))
+    }
+    // The run hook may return the argv of the program to execute, the trailing args are appended to it
+    fn on_run(&self, subc: &ArgMatches, ws: &Workspace) {
+        let args = &self.unmatched_args;
+        let request = Self::request(subc, args);
+        if subc.get_flag("watch") {
+            self.watch(
+                subc,
+                ws,
+                &request,
+                |ws| vec![Self::run_target(subc, ws)],
+                true,
+            );
+        }
+        let r = Self::run_target(subc, ws);
+        Self::execute(
+            build::generate(ws, std::slice::from_ref(&r), &request),
+            ws,
+            subc,
+        );
+
+        if let Some(program) = build::run_command(ws, &r, &request) {
+            self.run_program(program, subc);
+        }
+    }
+    // Exits with the code of the program, the trailing args are appended to it
+    fn run_program(&self, mut program: Vec<String>, subc: &ArgMatches) -> ! {
+        program.extend_from_slice(&self.unmatched_args);
+        if subc.get_flag("dry-run") {
+            say!("Would run: {}", program.join(" "));
+            exit(0);
+        }
+        let mut task = Task::new(program).interactive().non_fatal();
+        task.run();
+        report::program_started(&task);
+        task.wait();
+        report::program_finished(&task);
+        exit(task.exit_code().unwrap_or(1));
+    }
+    /*
+    Builds the selected test targets and runs them, the output of the failed
+    ones is printed after all of them finished. Tests are sharded in the order
+    of their labels, so that every CI machine agrees on the shards.
+    */
+    fn on_test(&self, subc: &ArgMatches, ws: &Workspace) {
+        let request = Self::request(subc, &self.unmatched_args);
+        let filters: Vec<String> = subc
+            .get_many::<String>("filters")
+            .unwrap_or_default()
+            .chain(subc.get_many::<String>("filter").unwrap_or_default())
+            .cloned()
+            .collect();
+        let mut tests: Vec<(String, TargetRef)> = ws
+            .tests()
+            .into_iter()
+            .map(|r| (ws.label(&r), r))
+            .filter(|(label, _)| testing::matches(label, &filters))
+            .collect();
+        tests.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(shard) = subc.get_one::<String>("shard") {
+            tests = testing::shard(tests, testing::parse_shard(shard));
+        }
+        if subc.get_flag("list") {
+            for (label, _) in &tests {
+                say!("{label}");
+            }
+            return;
+        }
+        if tests.is_empty() {
+            say!("No tests to run");
+            return;
+        }
+        let tests: Vec<TargetRef> = tests.into_iter().map(|(_, r)| r).collect();
+        Self::execute(build::generate(ws, &tests, &request), ws, subc);
+
+        let timeout = subc
+            .get_one::<u64>("timeout")
+            .map(|t| Duration::from_secs(*t));
+        let cases = testing::cases(ws, &tests, &request, &self.unmatched_args, timeout);
+        say!("Running {} tests", cases.len());
+        let results = testing::run(&cases, subc.get_one::<usize>("jobs").copied());
+        for result in &results {
+            report::emit("test-finished", result.to_json());
+            let status = if result.outcome.passed() {
+                "PASS"
+            } else {
+                "FAIL"
+            };
+            say!(
+                "{status} {} ({:.2}s)",
+                result.label,
+                result.duration.as_secs_f64()
+            );
+        }
+        let failed: Vec<&TestResult> = results.iter().filter(|r| !r.outcome.passed()).collect();
+        for result in &failed {
+            say!("\n---- {} {} ----", result.label, result.outcome.message());
+            for line in &result.output {
+                say!("{line}");
+            }
+        }
+        let timed_out = failed
+            .iter()
+            .filter(|r| matches!(r.outcome, Outcome::TimedOut(_)))
+            .count();
+        let passed = results.len() - failed.len();
+        say!(
+            "\n{passed} passed, {} failed, {timed_out} timed out",
+            failed.len() - timed_out
+        );
+        report::test_summary(passed, failed.len() - timed_out, timed_out);
+
+        let reports = [
+            ("junit", testing::junit as fn(&[TestResult]) -> String),
+            ("tap", testing::tap),
+        ];
+        for (id, report) in reports {
+            if let Some(path) = subc.get_one::<PathBuf>(id) {
+                fs::write(path, report(&results))
+                    .log(LiebeError::CannotWriteFile(path.display().to_string()));
+                say!("Wrote {}", path.display());
+            }
+        }
+        if !failed.is_empty() {
+            exit(1);
+        }
+    }
+    // Removes what tasks recorded as written, `--all` also drops the state and the cache dir
+    fn on_clean(subc: &ArgMatches, ws: &Workspace) {
+        let selection = Selection {
+            targets: subc
+                .get_many::<String>("target")
+                .unwrap_or_default()
+                .flat_map(|t| ws.resolve_many(t))
+                .map(|r| ws.label(&r))
+                .collect(),
+            dirs: subc
+                .get_one::<String>("profile")
+                .map(|profile| {
+                    (0..ws.projects.len())
+                        .map(|id| ws.build_dir(id, profile))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        let cache_dir = ws.cache_dir();
+        let mut state = StateDb::load(&cache_dir);
+        let all = subc.get_flag("all");
+        if subc.get_flag("dry-run") {
+            let files = clean::files_to_remove(&state, &selection);
+            for file in &files {
+                println!("Would remove {file}");
+            }
+            if all && cache_dir.exists() {
+                println!("Would remove {}", cache_dir.display());
+            }
+            println!("{} files would be removed", files.len());
+            return;
+        }
+        let removed = clean::clean(&mut state, &selection, &ws.root);
+        for file in &removed {
+            println!("Removed {file}");
+        }
+        println!("{} files removed", removed.len());
+        if all && cache_dir.exists() {
+            // The socket of a running daemon is in the cache dir
+            #[cfg(target_os = "linux")]
+            lcore::daemon::stop(&cache_dir);
+            fs::remove_dir_all(&cache_dir)
+                .log(LiebeError::CannotWriteFile(cache_dir.display().to_string()));
+            println!("Removed {}", cache_dir.display());
+        }
+    }
+    /*
+    Builds the targets and installs what they and their dependencies declared,
+    under DESTDIR when packaging. `--destdir` falls back to the DESTDIR
+    environment variable, as with make.
+    */
+    fn on_install(&self, subc: &ArgMatches, ws: &Workspace) {
+        let request = Self::request(subc, &self.unmatched_args);
+        let graph = build::generate(ws, &Self::requested(subc, ws), &request);
+        let mut rules = graph.installs.clone();
+        Self::execute(graph, ws, subc);
+        // Files their task wrote somewhere else than declared are installed from there
+        let state = StateDb::load(&ws.cache_dir());
+        for file in rules.iter_mut().flat_map(|rule| &mut rule.files) {
+            *file = state.actual(file).to_string();
+        }
+        if rules.is_empty() {
+            println!("Nothing to install");
+            return;
+        }
+        let cwd = env::current_dir().log(LiebeError::CannotOpenFile("current directory"));
+        let prefix = cwd.join(
+            subc.get_one::<PathBuf>("prefix")
+                .expect("prefix has a default"),
+        );
+        let destdir = subc.get_one::<PathBuf>("destdir").cloned().or_else(|| {
+            env::var_os("DESTDIR")
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from)
+        });
+        let root = match destdir {
+            Some(destdir) => cwd
+                .join(destdir)
+                .join(prefix.strip_prefix("/").unwrap_or(&prefix)),
+            None => prefix,
+        };
+        let mut manifest = Manifest::load(&ws.cache_dir());
+        for dest in install::install(&rules, &root, &mut manifest) {
+            println!("Installed {}", dest.display());
+        }
+    }
+    fn on_uninstall(ws: &Workspace) {
+        let mut manifest = Manifest::load(&ws.cache_dir());
+        if manifest.is_empty() {
+            println!("Nothing was installed");
+            return;
+        }
+        for path in install::uninstall(&mut manifest) {
+            println!("Removed {path}");
+        }
+    }
+    // Prints the logs of the last build, without a task or `--failed` lists its tasks
+    fn on_log(subc: &ArgMatches, ws: &Workspace) {
+        let Some(run) = LastRun::load(&ws.cache_dir()) else {
+            println!("No task ran yet");
+            return;
+        };
+        let entries = match subc.get_one::<String>("task") {
+            Some(task) => run.find(task),
+            None if subc.get_flag("failed") => run.failed(),
+            None => {
+                for entry in &run.entries {
+                    let status = if entry.success { "ok" } else { "FAILED" };
+                    println!("{status:<7}{} ({})", entry.name, entry.target);
+                }
+                println!("Logs are in {}", run.dir.display());
+                return;
+            }
+        };
+        if entries.is_empty() {
+            println!("No task failed in the last build");
+        }
+        for entry in entries {
+            println!("==> {} ({}) <==", entry.name, entry.target);
+            print!("{}", run.read(entry));
+        }
+    }
+    fn on_targets(ws: &Workspace) {
+        let targets: Vec<(String, &Target)> = ws
+            .all_targets()
+            .iter()
+            .map(|r| (ws.label(r), ws.project(r).targets.get(&r.name)))
+            .collect();
+        let width = targets
+            .iter()
+            .map(|(l, _)| l.len())
+            .max()
+            .unwrap_or_default();
+        for (label, target) in targets {
+            let kind = match target.default {
+                true => format!("{} (default)", target.kind.as_str()),
+                false => target.kind.as_str().to_string(),
+            };
+            let line = format!(
+                "{label:<width$}  {kind:<20}  {}",
+                target.description.as_deref().unwrap_or_default()
+            );
+            println!("{}", line.trim_end());
+        }
+    }
+    // The tasks the lang-scripts generate for the targets and what they depend on
+    fn on_tasks(&self, subc: &ArgMatches, ws: &Workspace) {
+        let request = Self::request(subc, &self.unmatched_args);
+        let graph = build::generate(ws, &Self::requested(subc, ws), &request);
+        if subc.get_flag("json") {
+            println!("{:#}", graph.to_json());
+            return;
+        }
+        let ids = |ids: &[usize]| {
+            ids.iter()
+                .map(|id| format!("#{id}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        for (id, spec) in graph.tasks.iter().enumerate() {
+            println!("#{id} {} ({})", spec.name, spec.target);
+            let cmd: Vec<String> = spec.cmd.iter().map(|a| shell_quote(a)).collect();
+            let env: Vec<String> = spec.env.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            let depfile = match &spec.depfile {
+                Some(DepStyle::Make(path)) => path.clone(),
+                Some(DepStyle::Msvc) => "/showIncludes".into(),
+                None => String::new(),
+            };
+            let fields = [
+                ("cmd", cmd.join(" ")),
+                ("cwd", spec.cwd.clone().unwrap_or_default()),
+                ("env", env.join(" ")),
+                ("inputs", spec.inputs.join(" ")),
+                ("outputs", spec.outputs.join(" ")),
+                ("deps", ids(&spec.deps)),
+                ("after", ids(&spec.order_deps)),
+                ("depfile", depfile),
+                ("pool", spec.pool.clone().unwrap_or_default()),
+                (
+                    "diagnostics",
+                    spec.diagnostics
+                        .map(|d| d.name().to_string())
+                        .unwrap_or_default(),
+                ),
+            ];
+            for (name, value) in fields.iter().filter(|(_, v)| !v.is_empty()) {
+                println!("    {:<13}{value}", format!("{name}:"));
+            }
+        }
+    }
+    // Targets as an indented tree, a target already shown is not expanded again
+    fn print_tree(ws: &Workspace, r: &TargetRef, depth: usize, shown: &mut Vec<TargetRef>) {
+        let expanded = shown.contains(r);
+        let more = if expanded && !ws.dep_refs(r).is_empty() {
+            " ..."
+        } else {
+            ""
+        };
+        println!("{}{}{more}", "  ".repeat(depth), ws.label(r));
+        if expanded {
+            return;
+        }
+        shown.push(r.clone());
+        for dep in ws.dep_refs(r) {
+            Self::print_tree(ws, &dep, depth + 1, shown);
+        }
+    }
+    fn on_graph(&self, subc: &ArgMatches, ws: &Workspace) {
+        let requested = Self::requested(subc, ws);
+        let requested = if requested.is_empty() {
+            ws.defaults()
+        } else {
+            requested
+        };
+        if subc.get_flag("tasks") {
+            let request = Self::request(subc, &self.unmatched_args);
+            print!("{}", dot::tasks(&build::generate(ws, &requested, &request)));
+        } else if subc.get_flag("dot") {
+            print!("{}", dot::targets(ws, &ws.build_order(&requested)));
+        } else {
+            let mut shown = Vec::new();
+            for r in &requested {
+                Self::print_tree(ws, r, 0, &mut shown);
+            }
+        }
+    }
+    fn on_profiles(ws: &Workspace) {
+        let profiles = Profiles::from_config(ws.current().lua.config());
+        for name in profiles.names() {
+            let profile = profiles.resolve(&name);
+            let inherits = profile
+                .inherits
+                .as_ref()
+                .map(|p| format!(" (inherits {p})"))
+                .unwrap_or_default();
+            println!(
+                "{name}{inherits}: {}",
+                profile.description.as_deref().unwrap_or("-")
+            );
+            println!(
+                "    opt-level={} debug={} defines=[{}] flags=[{}]",
+                profile.opt_level.as_deref().unwrap_or("0"),
+                profile.debug.unwrap_or(false),
+                profile.defines.join(", "),
+                profile.flags.join(" ")
+            );
+        }
+    }
+
+    fn message_format() -> Arg {
+        Arg::new("message-format")
+            .long("message-format")
+            .value_name("FORMAT")
+            .help(
+                "Print newline-delimited JSON events on stdout and the text on stderr with `json`",
+            )
+            .value_parser(["human", "json"])
+            .default_value("human")
+    }
+
+    fn exec_args(cmd: Command) -> Command {
+        cmd.arg(Self::message_format())
+            .arg(
+                Arg::new("watch")
+                    .long("watch")
+                    .short('w')
+                    .help("Build again whenever a source, liebe.toml or lua script changes")
+                    .conflicts_with("dry-run")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("dry-run")
+                    .long("dry-run")
+                    .short('n')
+                    .help("Print the tasks that would run without running them")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("explain")
+                    .long("explain")
+                    .help("Print why each task has to run")
+                    .action(ArgAction::SetTrue),
+            )
+    }
+
+    fn test_args(cmd: Command) -> Command {
+        cmd.arg(Self::message_format())
+            .arg(
+                Arg::new("filters")
+                    .help("Run the tests whose label contains FILTER or matches it as a glob")
+                    .value_name("FILTER")
+                    .num_args(0..)
+                    .index(1),
+            )
+            .arg(
+                Arg::new("filter")
+                    .long("filter")
+                    .value_name("FILTER")
+                    .help("Same as the FILTER arguments")
+                    .action(ArgAction::Append),
+            )
+            .arg(
+                Arg::new("list")
+                    .long("list")
+                    .help("List the selected tests without building them")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("shard")
+                    .long("shard")
+                    .value_name("I/N")
+                    .help("Run only the I-th of N equal parts of the tests"),
+            )
+            .arg(
+                Arg::new("timeout")
+                    .long("timeout")
+                    .value_name("SECONDS")
+                    .help("Fail tests running longer, unless their target sets a timeout")
+                    .value_parser(clap::value_parser!(u64)),
+            )
+            .arg(
+                Arg::new("jobs")
+                    .long("jobs")
+                    .short('j')
+                    .value_name("N")
+                    .help("Run at most N tests at once, defaults to the number of CPUs")
+                    .value_parser(clap::value_parser!(usize)),
+            )
+            .arg(
+                Arg::new("junit")
+                    .long("junit")
+                    .value_name("FILE")
+                    .help("Write a JUnit XML report")
+                    .value_parser(clap::value_parser!(PathBuf)),
+            )
+            .arg(
+                Arg::new("tap")
+                    .long("tap")
+                    .value_name("FILE")
+                    .help("Write a TAP report")
+                    .value_parser(clap::value_parser!(PathBuf)),
+            )
     }
-    fn on_run(target: &String, lua: &LuaApi) {
-        let context = lua.create_table();
-        context
-            .set("target", target.to_string())
-            .expect("Couldnt set value to run_conf");
-        lua.add_context("run_conf", context);
-        lua.call::<()>("run");
+
+    fn gen_args(cmd: Command, file: &str) -> Command {
+        Self::build_args(
+            cmd.arg(
+                Arg::new("targets")
+                    .help("Targets to include, defaults to the default targets")
+                    .num_args(0..)
+                    .index(1),
+            )
+            .arg(
+                Arg::new("output")
+                    .long("output")
+                    .short('o')
+                    .value_name("FILE")
+                    .help(format!(
+                        "Where to write it, defaults to {file} at the workspace root"
+                    ))
+                    .value_parser(clap::value_parser!(PathBuf)),
+            ),
+        )
+    }
+
+    fn build_args(cmd: Command) -> Command {
+        cmd.arg(
+            Arg::new("profile")
+                .long("profile")
+                .short('p')
+                .help("Profile to build with. eg- debug, release")
+                .default_value(DEFAULT_PROFILE),
+        )
+        .arg(
+            Arg::new("define")
+                .short('D')
+                .value_name("OPTION=VALUE")
+                .help("Set a build option declared in liebe.toml")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("args")
+                .help("Arguments passed to the lang-script, and to the program on `run`")
+                .value_name("ARGS")
+                .index(2)
+                .num_args(0..)
+                .last(true)
+                .allow_hyphen_values(true),
+        )
     }
 
     pub fn parse() -> Self {
@@ -52,22 +1051,234 @@ impl Cli {
             .version(VERSION)
             .author("coppamocha")
             .about("A next-generation build system without a headache")
-            .subcommand(
-                Command::new("run").about("Build and run the project").arg(
+            .subcommand(Self::exec_args(Self::build_args(
+                Command::new("run").about("Build and run a target").arg(
                     Arg::new("target")
-                        .help("Target to build (and run). eg- debug, release")
-                        .required(true)
+                        .help("Target to build and run, defaults to the only runnable one")
                         .index(1),
                 ),
+            )))
+            .subcommand(Self::exec_args(Self::build_args(
+                Command::new("build").about("Build the project").arg(
+                    Arg::new("targets")
+                        .help("Targets to build, defaults to the default targets")
+                        .num_args(0..)
+                        .index(1),
+                ),
+            )))
+            .subcommand(Self::build_args(Self::test_args(
+                Command::new("test").about("Build and run the test targets"),
+            )))
+            .subcommand(Self::build_args(
+                Command::new("compdb")
+                    .about("Write compile_commands.json without building")
+                    .arg(
+                        Arg::new("targets")
+                            .help("Targets to include, defaults to the default targets")
+                            .num_args(0..)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("output")
+                            .long("output")
+                            .short('o')
+                            .value_name("FILE")
+                            .help("Where to write it, defaults to the workspace root")
+                            .value_parser(clap::value_parser!(PathBuf)),
+                    ),
+            ))
+            .subcommand(
+                Command::new("clean")
+                    .about("Remove the files liebe built")
+                    .arg(
+                        Arg::new("target")
+                            .long("target")
+                            .short('t')
+                            .value_name("TARGET")
+                            .help("Only remove what the tasks of TARGET built")
+                            .action(ArgAction::Append),
+                    )
+                    .arg(
+                        Arg::new("profile")
+                            .long("profile")
+                            .short('p')
+                            .value_name("PROFILE")
+                            .help("Only remove what was built with PROFILE"),
+                    )
+                    .arg(
+                        Arg::new("dry-run")
+                            .long("dry-run")
+                            .short('n')
+                            .help("Print the files that would be removed")
+                            .action(ArgAction::SetTrue),
+                    )
+                    .arg(
+                        Arg::new("all")
+                            .long("all")
+                            .help("Also remove the state database and the cache dir")
+                            .conflicts_with_all(["target", "profile"])
+                            .action(ArgAction::SetTrue),
+                    ),
             )
+            .subcommand(Self::build_args(
+                Command::new("install")
+                    .about("Build and install targets with what they depend on")
+                    .arg(
+                        Arg::new("targets")
+                            .help("Targets to install, defaults to the default targets")
+                            .num_args(0..)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("prefix")
+                            .long("prefix")
+                            .value_name("DIR")
+                            .help("Directory to install to")
+                            .default_value("/usr/local")
+                            .value_parser(clap::value_parser!(PathBuf)),
+                    )
+                    .arg(
+                        Arg::new("destdir")
+                            .long("destdir")
+                            .value_name("DIR")
+                            .help("Staging directory the prefix is put in, eg. to package")
+                            .value_parser(clap::value_parser!(PathBuf)),
+                    ),
+            ))
+            .subcommand(Command::new("uninstall").about("Remove the files the last installs wrote"))
             .subcommand(
-                Command::new("build").about("Build the project").arg(
-                    Arg::new("target")
-                        .help("Target to build. eg- debug, release")
+                Command::new("log")
+                    .about("Print the output of the tasks of the last build")
+                    .arg(
+                        Arg::new("task")
+                            .help("Task to print the output of, by name or part of it")
+                            .conflicts_with("failed")
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("failed")
+                            .long("failed")
+                            .help("Print the output of the tasks that failed")
+                            .action(ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(
+                Command::new("init")
+                    .about("Create a liebe project")
+                    .arg(
+                        Arg::new("dir")
+                            .help("Directory of the project, created if needed")
+                            .default_value(".")
+                            .value_parser(clap::value_parser!(PathBuf))
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("lang")
+                            .long("lang")
+                            .short('l')
+                            .help("Language of the project")
+                            .value_parser(scaffold::languages())
+                            .default_value("c"),
+                    )
+                    .arg(
+                        Arg::new("name")
+                            .long("name")
+                            .help("Name of the project, defaults to the name of its directory"),
+                    ),
+            )
+            .subcommand(Command::new("targets").about("List the targets of the workspace"))
+            .subcommand(Self::build_args(
+                Command::new("tasks")
+                    .about("Print the tasks the lang-scripts generate for targets")
+                    .arg(
+                        Arg::new("targets")
+                            .help("Targets to show, defaults to the default targets")
+                            .num_args(0..)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("json")
+                            .long("json")
+                            .help("Print them as JSON")
+                            .action(ArgAction::SetTrue),
+                    ),
+            ))
+            .subcommand(Self::build_args(
+                Command::new("graph")
+                    .about("Print the dependencies between targets")
+                    .arg(
+                        Arg::new("targets")
+                            .help("Targets to show, defaults to the default targets")
+                            .num_args(0..)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("dot")
+                            .long("dot")
+                            .help("Print a Graphviz graph")
+                            .action(ArgAction::SetTrue),
+                    )
+                    .arg(
+                        Arg::new("tasks")
+                            .long("tasks")
+                            .help("Print a Graphviz graph of the tasks instead of the targets")
+                            .action(ArgAction::SetTrue),
+                    ),
+            ))
+            .subcommand(Command::new("profiles").about("List the build profiles"))
+            .subcommand(
+                Command::new("gen")
+                    .about("Export the build to another build tool")
+                    .subcommand_required(true)
+                    .subcommand(Self::gen_args(
+                        Command::new("ninja").about("Write a build.ninja"),
+                        NINJA_FILE,
+                    ))
+                    .subcommand(Self::gen_args(
+                        Command::new("make").about("Write a GNU Makefile"),
+                        MAKE_FILE,
+                    )),
+            )
+            .arg(
+                Arg::new("directory")
+                    .short('C')
+                    .long("directory")
+                    .value_name("DIR")
+                    .help("Change to DIR before doing anything")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .global(true),
+            )
+            .arg(
+                Arg::new("config")
+                    .long("config")
+                    .value_name("FILE")
+                    .help("Use FILE instead of looking for liebe.toml")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .global(true),
+            )
+            .subcommand(
+                Command::new("daemon")
+                    .about("Keep the workspace loaded in a background process")
+                    .subcommand_required(true)
+                    .subcommand(Command::new("start").about("Start the daemon"))
+                    .subcommand(Command::new("stop").about("Stop the daemon"))
+                    .subcommand(Command::new("status").about("Tell whether the daemon runs"))
+                    .subcommand(Command::new("serve").hide(true)),
+            )
+            .subcommand(
+                Command::new("depfile").hide(true).arg(
+                    Arg::new("file")
                         .required(true)
-                        .index(1),
+                        .value_parser(clap::value_parser!(PathBuf)),
                 ),
             )
+            .arg(
+                Arg::new("no-daemon")
+                    .long("no-daemon")
+                    .help("Load the workspace even if a daemon is running")
+                    .action(ArgAction::SetTrue)
+                    .global(true),
+            )
             .arg(
                 Arg::new("verbose")
                     .long("verbose")
@@ -75,21 +1286,24 @@ impl Cli {
                     .help("Allow a verbose output")
                     .action(ArgAction::SetTrue),
             )
-            .arg(
-                Arg::new("lua-args")
-                    .num_args(0..)
-                    .trailing_var_arg(true)
-                    .allow_hyphen_values(true),
-            )
             .get_matches();
-        let unmatched_args = matches
-            .get_many::<String>("lua-args")
+        // Everything after `--` on build/run, or on the backend of gen
+        let mut subc = matches.subcommand().map(|(_, subc)| subc);
+        if let Some(("gen", backends)) = matches.subcommand() {
+            subc = backends.subcommand().map(|(_, subc)| subc);
+        }
+        let format =
+            subc.and_then(|subc| subc.try_get_one::<String>("message-format").ok().flatten());
+        report::set_json(format.is_some_and(|f| f == "json"));
+        let unmatched_args = subc
+            .and_then(|subc| subc.try_get_many::<String>("args").ok().flatten())
             .unwrap_or_default()
             .cloned()
             .collect::<Vec<String>>();
         Self {
             unmatched_args,
             matches,
+            start_dir: env::current_dir().unwrap_or_default(),
         }
     }
 }